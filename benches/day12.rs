@@ -0,0 +1,42 @@
+#![feature(test)]
+
+extern crate test;
+
+use aoc2023::day12::*;
+use test::Bencher;
+
+const PERSONAL: &str = include_str!("../src/input/day12-real.txt");
+
+fn records(num_folds: usize) -> Vec<Record> {
+    PERSONAL.lines()
+        .map(|line| Record::parse(line, num_folds).expect("Should have been a valid record"))
+        .collect()
+}
+
+#[bench]
+fn sequential_with_fresh_tables(b: &mut Bencher) {
+    let records = records(5);
+    b.iter(|| {
+        records.iter()
+            .map(|record| {
+                let table = record.table_as::<usize>().expect("Arrangement count overflowed");
+                table.last().and_then(|row| row.last()).copied().unwrap_or(0)
+            })
+            .sum::<usize>()
+    });
+}
+
+#[bench]
+fn sequential_with_scratch(b: &mut Bencher) {
+    let records = records(5);
+    b.iter(|| {
+        let mut scratch = Scratch::new();
+        records.iter().map(|record| record.count_arrangements_with(&mut scratch)).sum::<usize>()
+    });
+}
+
+#[bench]
+fn parallel_with_scratch(b: &mut Bencher) {
+    let records = records(5);
+    b.iter(|| count_all(&records).into_iter().sum::<usize>());
+}
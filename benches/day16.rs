@@ -0,0 +1,34 @@
+#![feature(test)]
+
+extern crate test;
+
+use aoc2023::day16::*;
+use test::Bencher;
+
+const PERSONAL: &str = include_str!("../src/input/day16-real.txt");
+
+#[bench]
+fn best_entry_by_simulation(b: &mut Bencher) {
+    let grid = Grid::parse(PERSONAL).expect("Should have been a valid contraption");
+    b.iter(|| {
+        grid.entry_points()
+            .map(|(pos, direction)| grid.energize(pos, direction).len())
+            .max()
+    });
+}
+
+#[bench]
+fn best_entry_by_dense_simulation(b: &mut Bencher) {
+    let grid = Grid::parse(PERSONAL).expect("Should have been a valid contraption");
+    b.iter(|| {
+        grid.entry_points()
+            .map(|(pos, direction)| grid.energize_dense(pos, direction).len())
+            .max()
+    });
+}
+
+#[bench]
+fn best_entry_by_graph(b: &mut Bencher) {
+    let grid = Grid::parse(PERSONAL).expect("Should have been a valid contraption");
+    b.iter(|| BeamGraph::new(&grid).best_entry(&grid).map(|(_, _, count)| count));
+}
@@ -1,58 +1,338 @@
 //! Day 10 - Pipe Maze
 
-use std::collections::{HashSet, VecDeque};
+use std::{collections::HashSet, fmt::Display};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [Self::North, Self::East, Self::South, Self::West];
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::North => Self::South,
+            Self::East => Self::West,
+            Self::South => Self::North,
+            Self::West => Self::East,
+        }
+    }
+
+    /// The location one step away in this direction, if it isn't off the
+    /// top or left of the map
+    pub fn step(self, (row, col): (usize, usize)) -> Option<(usize, usize)> {
+        match self {
+            Self::North => Some((row.checked_sub(1)?, col)),
+            Self::East => Some((row, col + 1)),
+            Self::South => Some((row + 1, col)),
+            Self::West => Some((row, col.checked_sub(1)?)),
+        }
+    }
+}
+
+/// A single tile of the maze
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Pipe {
+    /// `|`
+    Vertical,
+    /// `-`
+    Horizontal,
+    /// `L`
+    NorthEast,
+    /// `J`
+    NorthWest,
+    /// `7`
+    SouthWest,
+    /// `F`
+    SouthEast,
+    /// `.`
+    Ground,
+}
+
+impl Pipe {
+    pub const ALL: [Pipe; 7] = [
+        Self::Vertical, Self::Horizontal, Self::NorthEast, Self::NorthWest,
+        Self::SouthWest, Self::SouthEast, Self::Ground,
+    ];
+
+    /// Which directions this tile has openings in
+    ///
+    /// ```
+    /// # use aoc2023::day10::*;
+    /// assert_eq!(Pipe::SouthEast.connections(), &[Direction::East, Direction::South]);
+    /// assert!(Pipe::Ground.connections().is_empty());
+    /// ```
+    pub fn connections(self) -> &'static [Direction] {
+        match self {
+            Self::Vertical => &[Direction::North, Direction::South],
+            Self::Horizontal => &[Direction::East, Direction::West],
+            Self::NorthEast => &[Direction::North, Direction::East],
+            Self::NorthWest => &[Direction::North, Direction::West],
+            Self::SouthWest => &[Direction::South, Direction::West],
+            Self::SouthEast => &[Direction::East, Direction::South],
+            Self::Ground => &[],
+        }
+    }
+
+    pub fn connects(self, dir: Direction) -> bool {
+        self.connections().contains(&dir)
+    }
+
+    /// Having come into this tile travelling in `heading`, which way do we
+    /// leave it?
+    pub fn exit(self, heading: Direction) -> Option<Direction> {
+        let entry = heading.opposite();
+        if !self.connects(entry) {
+            return None;
+        }
+
+        self.connections().iter().copied().find(|&dir| dir != entry)
+    }
+
+    /// How this tile looks when drawn with box-drawing characters
+    pub fn as_box_drawing(self) -> char {
+        match self {
+            Self::Vertical => '│',
+            Self::Horizontal => '─',
+            Self::NorthEast => '└',
+            Self::NorthWest => '┘',
+            Self::SouthWest => '┐',
+            Self::SouthEast => '┌',
+            Self::Ground => '.',
+        }
+    }
+
+    pub fn as_byte(self) -> u8 {
+        match self {
+            Self::Vertical => b'|',
+            Self::Horizontal => b'-',
+            Self::NorthEast => b'L',
+            Self::NorthWest => b'J',
+            Self::SouthWest => b'7',
+            Self::SouthEast => b'F',
+            Self::Ground => b'.',
+        }
+    }
+}
+
+impl TryFrom<u8> for Pipe {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL.into_iter().find(|pipe| pipe.as_byte() == value).ok_or(value)
+    }
+}
+
+/// Where a tile sits relative to the loop
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Region {
+    Inside,
+    Outside,
+    Loop,
+}
+
+/// Ways the maze can fail to load
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LoadError {
+    /// A tile was something other than a pipe, ground or the start
+    UnknownTile(char),
+    /// A row was a different length to the first one
+    Ragged { row: usize },
+    /// There was no 'S' tile
+    NoStart,
+    /// There was more than one 'S' tile
+    MultipleStarts,
+    /// No shape of pipe at the start closes a loop through its neighbours
+    DisconnectedStart,
+    /// More than one shape of pipe at the start closes a loop
+    AmbiguousStart(Vec<Pipe>),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownTile(ch) => write!(f, "unknown tile {ch:?}"),
+            Self::Ragged { row } => write!(f, "row {row} has a different length to the first"),
+            Self::NoStart => write!(f, "no starting tile"),
+            Self::MultipleStarts => write!(f, "more than one starting tile"),
+            Self::DisconnectedStart => write!(f, "the starting tile isn't part of a loop"),
+            Self::AmbiguousStart(options) => write!(f, "the starting tile could be any of {options:?}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
 
 pub struct Map {
-    nodes: Vec<Vec<u8>>,
+    tiles: Vec<Vec<Pipe>>,
     start: (usize, usize),
 }
 
 impl Map {
-    pub fn load(input: &str) -> Self {
-        let mut nodes: Vec<_> = input.lines().map(|line| line.as_bytes().to_vec()).collect();
-
-        for (y, row) in nodes.iter_mut().enumerate() {
-            for (x, node) in row.iter_mut().enumerate() {
-                if *node == b'S' {
-                    *node = b'F';
-                    return Self { nodes, start: (x, y) }
+    /// Load the maze, working out what shape of pipe is hiding under the
+    /// start from the neighbours that connect to it
+    ///
+    /// ```
+    /// # use aoc2023::day10::*;
+    /// let map = Map::load(".....\n.F-7.\n.|.|.\n.L-S.\n.....").unwrap();
+    /// assert_eq!(map.tile(map.start()), Some(Pipe::NorthWest));
+    /// ```
+    pub fn load(input: &str) -> Result<Self, LoadError> {
+        let mut start = None;
+        let mut tiles = vec![];
+
+        for (row, line) in input.lines().enumerate() {
+            let mut tiles_in_row = vec![];
+            for (col, byte) in line.trim().bytes().enumerate() {
+                if byte == b'S' {
+                    if start.replace((row, col)).is_some() {
+                        return Err(LoadError::MultipleStarts);
+                    }
+                    tiles_in_row.push(Pipe::Ground);
+                } else {
+                    tiles_in_row.push(Pipe::try_from(byte).map_err(|unknown| LoadError::UnknownTile(unknown as char))?);
                 }
             }
+
+            if tiles.first().is_some_and(|first: &Vec<Pipe>| first.len() != tiles_in_row.len()) {
+                return Err(LoadError::Ragged { row });
+            }
+            tiles.push(tiles_in_row);
         }
 
-        unimplemented!("There was no starting node");
+        let start = start.ok_or(LoadError::NoStart)?;
+        let mut map = Self { tiles, start };
+        map.tiles[start.0][start.1] = map.infer_start()?;
+        Ok(map)
     }
 
-    pub fn extract_ring(&self) -> HashSet<(usize, usize)> {
-        let mut visited = HashSet::from([self.start]);
-        let mut q = VecDeque::from([self.edges_of(&self.start)[0]]);
+    /// Work out which pipe the start must be. Every neighbour that opens
+    /// towards the start is a candidate exit; following the pipes out that
+    /// way either breaks, or comes back to the start from some other side,
+    /// and the two sides together give the shape.
+    fn infer_start(&self) -> Result<Pipe, LoadError> {
+        let mut options = vec![];
 
-        while let Some(next) = q.pop_back() {
-            for edge in self.edges_of(&next) {
-                if visited.insert(edge) {
-                    q.push_front(edge);
-                }
+        for exit in Direction::ALL {
+            let Some(entry) = self.trace_from_start(exit) else {
+                continue;
+            };
+
+            let pipe = Pipe::ALL.into_iter()
+                .find(|pipe| pipe.connects(exit) && pipe.connects(entry))
+                .expect("Every pair of directions should have had a pipe");
+            if !options.contains(&pipe) {
+                options.push(pipe);
+            }
+        }
+
+        match options.len() {
+            0 => Err(LoadError::DisconnectedStart),
+            1 => Ok(options[0]),
+            _ => Err(LoadError::AmbiguousStart(options)),
+        }
+    }
+
+    /// Leave the start heading `exit` and follow the pipes. If they lead
+    /// back to the start, return the side of the start they come back into.
+    fn trace_from_start(&self, exit: Direction) -> Option<Direction> {
+        let mut heading = exit;
+        let mut current = self.start;
+
+        for _ in 0..self.rows() * self.cols() {
+            current = heading.step(current)?;
+            if current == self.start {
+                return (heading.opposite() != exit).then_some(heading.opposite());
             }
+
+            heading = self.tile(current)?.exit(heading)?;
         }
 
-        visited
+        None
+    }
+
+    pub fn start(&self) -> (usize, usize) {
+        self.start
+    }
+
+    /// The tile at the given location, or `None` if it's off the map
+    pub fn tile(&self, (row, col): (usize, usize)) -> Option<Pipe> {
+        self.tiles.get(row).and_then(|line| line.get(col)).copied()
     }
 
+    pub fn rows(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn cols(&self) -> usize {
+        self.tiles.first().map_or(0, Vec::len)
+    }
+
+    /// The loop that passes through the start, in the order it's travelled,
+    /// beginning with the start itself
+    pub fn extract_ring(&self) -> Vec<(usize, usize)> {
+        let mut ring = vec![self.start];
+        let mut heading = self.tile(self.start)
+            .and_then(|pipe| pipe.connections().first().copied())
+            .expect("The start should have been connected to something");
+        let mut current = self.start;
+
+        loop {
+            current = heading.step(current).expect("The loop should not have left the map");
+            if current == self.start {
+                break;
+            }
+
+            ring.push(current);
+            heading = self.tile(current)
+                .and_then(|pipe| pipe.exit(heading))
+                .expect("The loop should not have been broken");
+        }
+
+        ring
+    }
+
+    /// Count the tiles enclosed by the loop. The shoelace formula gives the
+    /// area of the polygon through the centres of the loop's tiles, and Pick's
+    /// theorem turns that into a count of the whole tiles inside it.
     pub fn calculate_inner_area(&self) -> usize {
         let ring = self.extract_ring();
+
+        // Only the bends are vertices of the polygon, but including every
+        // tile on the loop gives the same answer
+        let twice_area = ring.iter()
+            .zip(ring.iter().cycle().skip(1))
+            .map(|(&(r1, c1), &(r2, c2))| (r1 * c2) as i64 - (r2 * c1) as i64)
+            .sum::<i64>()
+            .unsigned_abs() as usize;
+
+        // A = I + B/2 - 1, so I = A - B/2 + 1. The area is at least B/2 - 1,
+        // so adding before subtracting keeps this from underflowing
+        (twice_area + 2 - ring.len()) / 2
+    }
+
+    /// Count the tiles enclosed by the loop by casting a diagonal ray from
+    /// every tile and counting how many times it crosses the loop
+    pub fn calculate_inner_area_by_ray_casting(&self) -> usize {
+        let ring = self.extract_ring().into_iter().collect::<HashSet<_>>();
         let mut area = 0;
 
-        for (y, row) in self.nodes.iter().enumerate() {
+        for (y, row) in self.tiles.iter().enumerate() {
             for (x, _) in row.iter().enumerate() {
                 // By definition, nodes on the loop are not enclosed
                 if ring.contains(&(y, x)) {
                     continue;
                 }
 
-                // Ray casting for the win
-                let count = (x..row.len()).zip(y..self.nodes.len()).filter(|&(x2, y2)| {
-                    let c = self.nodes[y2][x2];
-                    ring.contains(&(y2, x2)) && c != b'L' && c != b'7'
+                // Ray casting for the win. A diagonal ray heading south east
+                // only grazes L and 7 bends, so those don't count as crossings
+                let count = (x..row.len()).zip(y..self.tiles.len()).filter(|&(x2, y2)| {
+                    let c = self.tiles[y2][x2];
+                    ring.contains(&(y2, x2)) && c != Pipe::NorthEast && c != Pipe::SouthWest
                 }).count();
 
                 if count % 2 == 1 {
@@ -64,17 +344,81 @@ impl Map {
         area
     }
 
+    /// Work out which tiles are inside the loop, outside it, or part of it.
+    ///
+    /// Each tile is blown up into a 3x3 block, with the loop's pipes drawn
+    /// through the middle of their blocks. That opens up gaps between pipes
+    /// that run alongside each other, so a flood fill from the edge of the
+    /// map can squeeze between them and reach everything that's outside.
+    pub fn classify(&self) -> Vec<Vec<Region>> {
+        let ring = self.extract_ring();
+        let (rows, cols) = (self.rows() * 3, self.cols() * 3);
+
+        let mut walls = vec![vec![false; cols]; rows];
+        for &(row, col) in &ring {
+            let centre = (row * 3 + 1, col * 3 + 1);
+            walls[centre.0][centre.1] = true;
+
+            for &dir in self.tiles[row][col].connections() {
+                let (r, c) = dir.step(centre).expect("Should have stayed inside the block");
+                walls[r][c] = true;
+            }
+        }
+
+        // The loop can't open off the edge of the map, so the border of the
+        // blown up map is never a wall and the corner is a safe place to start
+        let mut outside = vec![vec![false; cols]; rows];
+        let mut stack = vec![(0, 0)];
+        outside[0][0] = true;
+
+        while let Some(next) = stack.pop() {
+            for dir in Direction::ALL {
+                let Some((r, c)) = dir.step(next).filter(|&(r, c)| r < rows && c < cols) else {
+                    continue;
+                };
+
+                if !walls[r][c] && !outside[r][c] {
+                    outside[r][c] = true;
+                    stack.push((r, c));
+                }
+            }
+        }
+
+        let ring = ring.into_iter().collect::<HashSet<_>>();
+        (0..self.rows())
+            .map(|row| {
+                (0..self.cols())
+                    .map(|col| {
+                        if ring.contains(&(row, col)) {
+                            Region::Loop
+                        } else if outside[row * 3 + 1][col * 3 + 1] {
+                            Region::Outside
+                        } else {
+                            Region::Inside
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Draw the maze with the loop in box-drawing characters, tiles inside
+    /// it as 'I' and tiles outside it as 'O'
+    pub fn render(&self) -> String {
+        let mut rendered = String::new();
 
-    fn edges_of(&self, &(x, y): &(usize, usize)) -> [(usize, usize); 2] {
-        match self.nodes[x][y] {
-            b'|' => [(x - 1, y),     (x + 1, y)],
-            b'-' => [(x,     y - 1), (x,     y + 1)],
-            b'L' => [(x - 1, y),     (x,     y + 1)],
-            b'J' => [(x - 1, y),     (x,     y - 1)],
-            b'7' => [(x + 1, y),     (x,     y - 1)],
-            b'F' => [(x + 1, y),     (x,     y + 1)],
-            unknown  => unimplemented!("What on earth is this {unknown} doing in the loop"),
+        for (row, regions) in self.classify().iter().enumerate() {
+            for (col, region) in regions.iter().enumerate() {
+                rendered.push(match region {
+                    Region::Loop => self.tiles[row][col].as_box_drawing(),
+                    Region::Inside => 'I',
+                    Region::Outside => 'O',
+                });
+            }
+            rendered.push('\n');
         }
+
+        rendered
     }
 }
 
@@ -93,12 +437,135 @@ mod answers {
     #[test_case(SAMPLE_INPUT_2 => 8; "with second sample data")]
     #[test_case(PERSONAL_INPUT => 6931; "with personal data")]
     pub fn problem1(input: &str) -> usize {
-        Map::load(input).extract_ring().len() / 2
+        Map::load(input).expect("Should have been a valid maze").extract_ring().len() / 2
     }
 
     #[test_case(SAMPLE_INPUT_3 => 4; "with third sample data")]
+    #[test_case("S7\nLJ" => 0; "with the smallest loop")]
     #[test_case(PERSONAL_INPUT => 357; "with personal data")]
     pub fn problem2(input: &str) -> usize {
-        Map::load(input).calculate_inner_area()
+        Map::load(input).expect("Should have been a valid maze").calculate_inner_area()
+    }
+
+    #[test_case(SAMPLE_INPUT_3 => 4; "with third sample data")]
+    #[test_case(PERSONAL_INPUT => 357; "with personal data")]
+    pub fn problem2_ray_casting(input: &str) -> usize {
+        Map::load(input).expect("Should have been a valid maze").calculate_inner_area_by_ray_casting()
+    }
+
+    // The tiles between the two inner pipes are outside, even though there's
+    // no gap between the pipes themselves
+    const SQUEEZE: &str = "..........
+                           .S------7.
+                           .|F----7|.
+                           .||....||.
+                           .||....||.
+                           .|L-7F-J|.
+                           .|..||..|.
+                           .L--JL--J.
+                           ..........";
+
+    const LARGER: &str = ".F----7F7F7F7F-7....
+                          .|F--7||||||||FJ....
+                          .||.FJ||||||||L7....
+                          FJL7L7LJLJ||LJ.L-7..
+                          L--J.L7...LJS7F-7L7.
+                          ....F-J..F7FJ|L7L7L7
+                          ....L7.F7||L7|.L7L7|
+                          .....|FJLJ|FJ|F7|.LJ
+                          ....FJL-7.||.||||...
+                          ....L---J.LJ.LJLJ...";
+
+    #[test_case(SAMPLE_INPUT_1 => 1; "with first sample data")]
+    #[test_case(SAMPLE_INPUT_2 => 1; "with second sample data")]
+    #[test_case(SAMPLE_INPUT_3 => 4; "with third sample data")]
+    #[test_case(SQUEEZE => 4; "with pipes squeezed together")]
+    #[test_case(LARGER => 8; "with a larger maze")]
+    #[test_case("S7\nLJ" => 0; "with the smallest loop")]
+    #[test_case(PERSONAL_INPUT => 357; "with personal data")]
+    pub fn problem2_cross_checked(input: &str) -> usize {
+        let map = Map::load(input).expect("Should have been a valid maze");
+        let regions = map.classify();
+        let count = |kind| regions.iter().flatten().filter(|&&region| region == kind).count();
+
+        assert_eq!(count(Region::Loop), map.extract_ring().len());
+        assert_eq!(count(Region::Inside) + count(Region::Outside) + count(Region::Loop), map.rows() * map.cols());
+        assert_eq!(count(Region::Inside), map.calculate_inner_area_by_ray_casting());
+        assert_eq!(count(Region::Inside), map.calculate_inner_area());
+        count(Region::Inside)
+    }
+
+    #[test]
+    pub fn render_sample() {
+        let map = Map::load(SAMPLE_INPUT_3).expect("Should have been a valid maze");
+        let expected = "\
+OOOOOOOOOOO
+O┌───────┐O
+O│┌─────┐│O
+O││OOOOO││O
+O││OOOOO││O
+O│└─┐O┌─┘│O
+O│II│O│II│O
+O└──┘O└──┘O
+OOOOOOOOOOO
+";
+        assert_eq!(map.render(), expected);
+    }
+
+    #[test]
+    pub fn ring_is_in_travel_order() {
+        let map = Map::load(SAMPLE_INPUT_1).expect("Should have been a valid maze");
+        let ring = map.extract_ring();
+
+        assert_eq!(ring, vec![(1, 1), (1, 2), (1, 3), (2, 3), (3, 3), (3, 2), (3, 1), (2, 1)]);
+
+        // Every tile follows on from the one before it
+        for (&a, &b) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+            assert_eq!(a.0.abs_diff(b.0) + a.1.abs_diff(b.1), 1);
+        }
+    }
+
+    #[test_case(b'|', Direction::North => Some(Direction::North); "straight through a vertical pipe")]
+    #[test_case(b'L', Direction::South => Some(Direction::East); "round an L bend")]
+    #[test_case(b'7', Direction::East => Some(Direction::South); "round a 7 bend")]
+    #[test_case(b'-', Direction::North => None; "into the side of a horizontal pipe")]
+    #[test_case(b'.', Direction::West => None; "onto the ground")]
+    pub fn pipe_exits(tile: u8, heading: Direction) -> Option<Direction> {
+        Pipe::try_from(tile).expect("Should have been a pipe").exit(heading)
+    }
+
+    #[test_case((0, 0) => Pipe::SouthEast; "as an F bend")]
+    #[test_case((0, 2) => Pipe::SouthWest; "as a 7 bend")]
+    #[test_case((2, 0) => Pipe::NorthEast; "as an L bend")]
+    #[test_case((2, 2) => Pipe::NorthWest; "as a J bend")]
+    #[test_case((0, 1) => Pipe::Horizontal; "as a horizontal pipe")]
+    #[test_case((1, 2) => Pipe::Vertical; "as a vertical pipe")]
+    pub fn start_shapes((row, col): (usize, usize)) -> Pipe {
+        let mut lines = ["F-7", "|.|", "L-J"].map(|line| line.to_owned());
+        lines[row].replace_range(col..=col, "S");
+
+        let map = Map::load(&lines.join("\n")).expect("Should have been a valid maze");
+        assert_eq!(map.extract_ring().len(), 8);
+        assert_eq!(map.calculate_inner_area(), 1);
+        map.tile(map.start()).expect("Should have had a start")
+    }
+
+    // Two loops meet at the start, so it could be a J or an F
+    const FIGURE_EIGHT: &str = "F-7..
+                                |.|..
+                                L-S-7
+                                ..|.|
+                                ..L-J";
+
+    #[test_case("...\n.S.\n..." => LoadError::DisconnectedStart; "with nothing around the start")]
+    #[test_case("-S-" => LoadError::DisconnectedStart; "with a dead end")]
+    #[test_case("S-7\n|.|\nL-S" => LoadError::MultipleStarts; "with two starts")]
+    #[test_case("F-7\n|.|\nL-J" => LoadError::NoStart; "without a start")]
+    #[test_case("S-7\n|X|\nL-J" => LoadError::UnknownTile('X'); "with an unknown tile")]
+    #[test_case("S-7\n|.|.\nL-J" => LoadError::Ragged { row: 1 }; "with a long row")]
+    #[test_case("S-7\n|.|\nL-" => LoadError::Ragged { row: 2 }; "with a short row")]
+    #[test_case(FIGURE_EIGHT => LoadError::AmbiguousStart(vec![Pipe::NorthWest, Pipe::SouthEast]); "with two loops")]
+    pub fn bad_mazes(input: &str) -> LoadError {
+        Map::load(input).err().expect("Should have failed to load")
     }
 }
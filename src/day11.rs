@@ -16,28 +16,211 @@ pub fn parse_image(input: &str) -> Universe {
     universe
 }
 
-pub fn expansion(universe: &mut Universe, factor: usize) {
-    let mut row_extents = (usize::MAX, usize::MIN);
-    let mut col_extents = (usize::MAX, usize::MIN);
-
-    universe.iter().for_each(|loc| {
-        row_extents = (row_extents.0.min(loc.0), row_extents.1.max(loc.0));
-        col_extents = (col_extents.0.min(loc.1), col_extents.1.max(loc.1));
-    });
-
-    for row in (row_extents.0..row_extents.1).rev() {
-        // If this row is empty, every row below it should be adjusted by
-        // factor
-        if !universe.iter().any(|x| x.0 == row) {
-            universe.iter_mut().filter(|j| j.0 > row).for_each(|k| k.0 += factor);
-        }
+/// For each galaxy's coordinate along one axis, how many empty lines lie
+/// between it and the first occupied line. Only the distinct occupied
+/// coordinates need to be looked at, so this is `O(n log n)` in the number
+/// of galaxies rather than proportional to the size of the image.
+fn empty_lines_before(coords: impl Iterator<Item = usize>) -> Vec<usize> {
+    let coords = coords.collect::<Vec<_>>();
+    let mut occupied = coords.clone();
+    occupied.sort_unstable();
+    occupied.dedup();
+
+    // The i'th occupied line has i occupied lines before it, and everything
+    // else between it and the first occupied line is empty
+    coords.iter()
+        .map(|coord| {
+            let idx = occupied.binary_search(coord).expect("Should have been occupied");
+            coord - occupied[0] - idx
+        })
+        .collect()
+}
+
+/// How much the empty space in the universe grows. Every empty row turns
+/// into `rows` rows, and every empty column into `cols` columns, so a factor
+/// of 1 leaves the universe as it is and 2 doubles the empty space.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Expansion {
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Default for Expansion {
+    fn default() -> Self {
+        Self::uniform(1)
+    }
+}
+
+impl Expansion {
+    /// Every empty row and column becomes `factor` of them
+    pub fn uniform(factor: usize) -> Self {
+        Self { rows: factor, cols: factor }
+    }
+
+    /// Empty rows and columns grow by different amounts
+    pub fn per_axis(rows: usize, cols: usize) -> Self {
+        Self { rows, cols }
     }
 
-    for col in (col_extents.0..col_extents.1).rev() {
-        if !universe.iter().any(|x| x.1 == col) {
-            universe.iter_mut().filter(|j| j.1 > col).for_each(|k| k.1 += factor);
+    /// Where something ends up when it has the given number of empty lines
+    /// before it along an axis that grows by `factor`
+    fn shift(coord: usize, empty: usize, factor: usize) -> usize {
+        coord - empty + empty * factor
+    }
+
+    /// Expand the universe in place
+    ///
+    /// ```
+    /// # use aoc2023::day11::*;
+    /// let mut universe = parse_image("#..\n...\n..#");
+    /// Expansion::per_axis(10, 2).expand(&mut universe);
+    /// assert_eq!(universe, vec![(0, 0), (11, 3)]);
+    /// ```
+    pub fn expand(&self, universe: &mut Universe) {
+        let empty_rows = empty_lines_before(universe.iter().map(|g| g.0));
+        let empty_cols = empty_lines_before(universe.iter().map(|g| g.1));
+
+        for (galaxy, (rows, cols)) in universe.iter_mut().zip(empty_rows.into_iter().zip(empty_cols)) {
+            galaxy.0 = Self::shift(galaxy.0, rows, self.rows);
+            galaxy.1 = Self::shift(galaxy.1, cols, self.cols);
         }
     }
+
+    /// Number the galaxies from 1 in reading order, as in the puzzle, and
+    /// pair each number up with where that galaxy ends up after expansion
+    ///
+    /// ```
+    /// # use aoc2023::day11::*;
+    /// let universe = parse_image("#..\n...\n..#");
+    /// assert_eq!(Expansion::uniform(2).galaxies(&universe), vec![(1, (0, 0)), (2, (3, 3))]);
+    /// ```
+    pub fn galaxies(&self, universe: &Universe) -> Vec<(usize, (usize, usize))> {
+        let mut expanded = universe.clone();
+        self.expand(&mut expanded);
+        (1..).zip(expanded).collect()
+    }
+}
+
+/// Expand the universe in place, adding `factor` extra rows for every empty
+/// row and `factor` extra columns for every empty column. This is the same
+/// as `Expansion::uniform(factor + 1)`.
+pub fn expansion(universe: &mut Universe, factor: usize) {
+    Expansion::uniform(factor + 1).expand(universe)
+}
+
+/// Sum of `|a - b|` over every pair of values, by sorting and keeping a
+/// running total of everything seen so far
+fn pairwise_total(values: impl Iterator<Item = usize>) -> usize {
+    let mut values = values.collect::<Vec<_>>();
+    values.sort_unstable();
+
+    let mut prefix = 0;
+    values.iter().enumerate().fold(0, |total, (idx, &value)| {
+        let total = total + value * idx - prefix;
+        prefix += value;
+        total
+    })
+}
+
+/// The sum of the Manhattan distances between every pair of galaxies
+///
+/// ```
+/// # use aoc2023::day11::*;
+/// assert_eq!(total_pairwise_distance(&vec![(0, 0), (1, 2), (3, 1)]), 3 + 4 + 3);
+/// ```
+pub fn total_pairwise_distance(universe: &Universe) -> usize {
+    pairwise_total(universe.iter().map(|g| g.0)) + pairwise_total(universe.iter().map(|g| g.1))
+}
+
+/// A galaxy's place in the image, along with how many empty rows and
+/// columns lie above and to the left of it
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Located {
+    row: usize,
+    col: usize,
+    empty_rows: usize,
+    empty_cols: usize,
+}
+
+impl Located {
+    fn expanded(&self, expansion: Expansion) -> (usize, usize) {
+        (
+            Expansion::shift(self.row, self.empty_rows, expansion.rows),
+            Expansion::shift(self.col, self.empty_cols, expansion.cols),
+        )
+    }
+}
+
+/// The galaxies in an image, prepared so that distances can be found under
+/// any expansion without expanding the universe again. Galaxies are
+/// numbered from 1 in reading order, as in the puzzle.
+pub struct Galaxies {
+    galaxies: Vec<Located>,
+    image_totals: (usize, usize),
+    empty_totals: (usize, usize),
+}
+
+impl Galaxies {
+    pub fn new(universe: &Universe) -> Self {
+        let empty_rows = empty_lines_before(universe.iter().map(|g| g.0));
+        let empty_cols = empty_lines_before(universe.iter().map(|g| g.1));
+
+        let galaxies = universe.iter().zip(empty_rows.into_iter().zip(empty_cols))
+            .map(|(&(row, col), (empty_rows, empty_cols))| Located { row, col, empty_rows, empty_cols })
+            .collect::<Vec<_>>();
+
+        // Expansion is monotonic, so along each axis every pairwise distance
+        // splits into the occupied lines crossed plus the factor times the
+        // empty lines crossed, and each of those can be totalled up once
+        let image_totals = (
+            pairwise_total(universe.iter().map(|g| g.0)),
+            pairwise_total(universe.iter().map(|g| g.1)),
+        );
+        let empty_totals = (
+            pairwise_total(galaxies.iter().map(|g| g.empty_rows)),
+            pairwise_total(galaxies.iter().map(|g| g.empty_cols)),
+        );
+
+        Self { galaxies, image_totals, empty_totals }
+    }
+
+    pub fn len(&self) -> usize {
+        self.galaxies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.galaxies.is_empty()
+    }
+
+    /// Where the galaxy with the given number ends up once the universe has
+    /// expanded
+    pub fn position(&self, id: usize, expansion: Expansion) -> Option<(usize, usize)> {
+        self.galaxies.get(id.checked_sub(1)?).map(|g| g.expanded(expansion))
+    }
+
+    /// The shortest path between two numbered galaxies once the universe has
+    /// expanded
+    ///
+    /// ```
+    /// # use aoc2023::day11::*;
+    /// let galaxies = Galaxies::new(&parse_image("#..\n...\n..#"));
+    /// assert_eq!(galaxies.distance(1, 2, Expansion::uniform(1)), Some(4));
+    /// assert_eq!(galaxies.distance(1, 2, Expansion::uniform(10)), Some(22));
+    /// assert_eq!(galaxies.distance(1, 2, Expansion::per_axis(10, 1)), Some(13));
+    /// assert_eq!(galaxies.distance(1, 3, Expansion::uniform(1)), None);
+    /// ```
+    pub fn distance(&self, a: usize, b: usize, expansion: Expansion) -> Option<usize> {
+        let (a, b) = (self.position(a, expansion)?, self.position(b, expansion)?);
+        Some(a.0.abs_diff(b.0) + a.1.abs_diff(b.1))
+    }
+
+    /// The sum of the distances between every pair of galaxies once the
+    /// universe has expanded, in constant time
+    pub fn total_distance(&self, expansion: Expansion) -> usize {
+        let axis = |image: usize, empty: usize, factor: usize| image - empty + empty * factor;
+        axis(self.image_totals.0, self.empty_totals.0, expansion.rows)
+            + axis(self.image_totals.1, self.empty_totals.1, expansion.cols)
+    }
 }
 
 #[cfg(test)]
@@ -56,15 +239,76 @@ mod answers {
     pub fn problem1(input: &str, factor: usize) -> usize {
         let mut universe = parse_image(input);
         expansion(&mut universe, factor);
+        total_pairwise_distance(&universe)
+    }
 
-        let mut distance = 0;
+    #[test_case(SAMPLE_INPUT, 2 => 374; "with sample data")]
+    #[test_case(PERSONAL_INPUT, 2 => 9_742_154; "with personal data")]
+    #[test_case(SAMPLE_INPUT, 10 => 1030; "with sample data x10")]
+    #[test_case(SAMPLE_INPUT, 100 => 8410; "with sample data x100")]
+    #[test_case(PERSONAL_INPUT, 1_000_000 => 411_142_919_886; "with personal data x1000000")]
+    pub fn problem1_without_expanding(input: &str, factor: usize) -> usize {
+        Galaxies::new(&parse_image(input)).total_distance(Expansion::uniform(factor))
+    }
 
-        for (n, x) in universe.iter().enumerate() {
-            for y in universe.iter().skip(n + 1) {
-                distance += x.0.abs_diff(y.0) + x.1.abs_diff(y.1);
+    #[test_case(SAMPLE_INPUT, Expansion::uniform(1); "with sample data unexpanded")]
+    #[test_case(SAMPLE_INPUT, Expansion::uniform(0); "with sample data collapsed")]
+    #[test_case(SAMPLE_INPUT, Expansion::uniform(100); "with sample data x100")]
+    #[test_case(SAMPLE_INPUT, Expansion::per_axis(3, 7); "with sample data x3 by x7")]
+    #[test_case(PERSONAL_INPUT, Expansion::per_axis(1_000_000, 2); "with personal data x1000000 by x2")]
+    pub fn matches_every_pair(input: &str, expansion: Expansion) {
+        let universe = parse_image(input);
+        let galaxies = Galaxies::new(&universe);
+
+        let mut distance = 0;
+        let expanded = expansion.galaxies(&universe);
+        for (n, &(a, x)) in expanded.iter().enumerate() {
+            assert_eq!(galaxies.position(a, expansion), Some(x));
+            for &(b, y) in expanded.iter().skip(n + 1) {
+                let between = x.0.abs_diff(y.0) + x.1.abs_diff(y.1);
+                assert_eq!(galaxies.distance(a, b, expansion), Some(between));
+                distance += between;
             }
         }
 
-        distance
+        assert_eq!(galaxies.total_distance(expansion), distance);
+    }
+
+    #[test_case(1, 2; "rows only")]
+    #[test_case(2, 1; "columns only")]
+    #[test_case(5, 5; "both")]
+    pub fn per_axis_combines_uniform(rows: usize, cols: usize) {
+        let mut by_axis = parse_image(SAMPLE_INPUT);
+        Expansion::per_axis(rows, cols).expand(&mut by_axis);
+
+        // Rows come from the row factor alone, and the same for columns
+        let mut rows_only = parse_image(SAMPLE_INPUT);
+        Expansion::uniform(rows).expand(&mut rows_only);
+        let mut cols_only = parse_image(SAMPLE_INPUT);
+        Expansion::uniform(cols).expand(&mut cols_only);
+
+        for ((actual, r), c) in by_axis.iter().zip(&rows_only).zip(&cols_only) {
+            assert_eq!(*actual, (r.0, c.1));
+        }
+    }
+
+    #[test_case(0; "unexpanded")]
+    #[test_case(9; "x10")]
+    pub fn wrapper_adds_extra_lines(factor: usize) {
+        let mut old = parse_image(SAMPLE_INPUT);
+        expansion(&mut old, factor);
+        let mut new = parse_image(SAMPLE_INPUT);
+        Expansion::uniform(factor + 1).expand(&mut new);
+        assert_eq!(old, new);
+    }
+
+    #[test_case(5, 9 => Some(9); "from galaxy 5 to galaxy 9")]
+    #[test_case(1, 7 => Some(15); "from galaxy 1 to galaxy 7")]
+    #[test_case(3, 6 => Some(17); "from galaxy 3 to galaxy 6")]
+    #[test_case(8, 9 => Some(5); "from galaxy 8 to galaxy 9")]
+    #[test_case(9, 8 => Some(5); "from galaxy 9 to galaxy 8")]
+    #[test_case(0, 1 => None; "from a galaxy that doesn't exist")]
+    pub fn sample_distances(a: usize, b: usize) -> Option<usize> {
+        Galaxies::new(&parse_image(SAMPLE_INPUT)).distance(a, b, Expansion::uniform(2))
     }
 }
@@ -1,100 +1,511 @@
 //! Day 12 - Hot Springs
 
 use crate::prelude::strings::*;
-use std::iter::once;
+use num_traits::{CheckedAdd, One, Zero};
+use std::{fmt::Display, iter::once};
 
 #[derive(Debug)]
 pub struct Record {
     spring_layout: Vec<char>,
     group_sizes: Vec<usize>,
+    trailing_operational: usize,
 }
 
+/// How many arrangements of a record have a particular unknown spring
+/// damaged, and how many have it operational
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CellCount {
+    /// Where the spring is in the (unfolded) record
+    pub position: usize,
+    pub damaged: usize,
+    pub operational: usize,
+}
+
+/// Anything arrangements can be counted in, e.g. `u64`, `u128` or a
+/// [`BigUint`](num_bigint::BigUint) when even those are too small
+pub trait Count: Clone + Zero + One + CheckedAdd {}
+
+impl<T: Clone + Zero + One + CheckedAdd> Count for T {}
+
+/// Ways that a line can fail to describe a record
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// The record was asked to be folded zero times
+    NoFolds,
+    /// The unfolded record would have more than [`Record::MAX_UNFOLDED`]
+    /// springs or groups
+    TooManyFolds,
+    /// There was no space between the springs and the group sizes
+    MissingGroups,
+    /// A spring was something other than '.', '#' or '?'
+    UnknownSpring(char),
+    /// A group size wasn't a positive number
+    BadGroupSize(String),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoFolds => write!(f, "a record has to be folded at least once"),
+            Self::TooManyFolds => write!(f, "too many folds to unfold the record"),
+            Self::MissingGroups => write!(f, "no group sizes after the springs"),
+            Self::UnknownSpring(ch) => write!(f, "unknown spring {ch:?}"),
+            Self::BadGroupSize(size) => write!(f, "bad group size {size:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 impl Record {
-    pub fn parse(input: &str, num_folds: usize) -> Self {
-        let (x, y) = input.split_once(' ').expect("Bad input");
+    /// The most springs, and separately the most groups, that an unfolded
+    /// record can have. That's far more than the puzzle needs, but keeps
+    /// a silly number of folds from trying to allocate the world.
+    pub const MAX_UNFOLDED: usize = 1 << 16;
+
+    /// Parse a record, unfolding it the given number of times
+    ///
+    /// ```
+    /// # use aoc2023::day12::*;
+    /// assert_eq!(Record::parse("???.### 1,1,3", 0).err(), Some(ParseError::NoFolds));
+    /// assert_eq!(Record::parse("???.### 1,x,3", 1).err(), Some(ParseError::BadGroupSize("x".into())));
+    /// assert_eq!(Record::parse("???.### 1,1,3", usize::MAX).err(), Some(ParseError::TooManyFolds));
+    /// ```
+    pub fn parse(input: &str, num_folds: usize) -> Result<Self, ParseError> {
+        let (x, y) = input.split_once(' ').ok_or(ParseError::MissingGroups)?;
+
+        if num_folds == 0 {
+            return Err(ParseError::NoFolds);
+        }
+        if let Some(ch) = x.chars().find(|ch| !matches!(ch, '.' | '#' | '?')) {
+            return Err(ParseError::UnknownSpring(ch));
+        }
+
+        let group_sizes = if y.is_empty() {
+            vec![]
+        } else {
+            y.split(',')
+                .map(|size| match size.parse() {
+                    Ok(size) if size > 0 => Ok(size),
+                    _ => Err(ParseError::BadGroupSize(size.to_owned())),
+                })
+                .collect::<Result<Vec<usize>, _>>()?
+        };
+
+        // Each fold adds a '?' between copies of the springs, so check how
+        // big everything will get before unfolding any of it
+        let unfolded = |count: usize| count.checked_mul(num_folds).filter(|&total| total <= Self::MAX_UNFOLDED);
+        if unfolded(x.len() + 1).is_none() || unfolded(group_sizes.len()).is_none() {
+            return Err(ParseError::TooManyFolds);
+        }
 
         // Pre-process the spring layout so we don't have to do it later. Trailing
         // empty spaces can be ignored, and add one at the beginning to make the
         // off-by-ones during counting less awful lol
         let x = once(x).cycle().take(num_folds).join("?");
-        let spring_layout = format!(".{}", x.trim_end_matches('.')).chars().collect();
+        let trimmed = x.trim_end_matches('.');
+        let trailing_operational = x.len() - trimmed.len();
+        let spring_layout = format!(".{trimmed}").chars().collect();
 
-        // For the group sizes, just split and duplicate them
-        let group_sizes = y.split(',').filter_map(|c| c.parse().ok()).collect::<Vec<_>>();
+        // For the group sizes, just duplicate them
         let num_groups = group_sizes.len();
         let group_sizes = group_sizes.into_iter().cycle().take(num_groups * num_folds).collect();
 
-        Self { spring_layout, group_sizes }
+        Ok(Self { spring_layout, group_sizes, trailing_operational })
     }
 
-    // I really fucking hate dynamic programming
     pub fn count_arrangements(&self) -> usize {
-        // The overall approach is to iteratively determine how many
-        // configurations there are for the first 0..n groupings in
-        // the first 0..m positions. When all is said and done, the last
-        // cell in the table should be the number of ways for all of the
-        // groupings to appear in all of the positions
-
-        // The first row of the table is the number of ways we can have
-        // zero springs in the first n positions. There's exactly one way
-        // to have nothing until we hit the first known spring, at which point
-        // there are zero ways
-        //
-        // The zeroth position is trivially 1 - there's one way to put nothing
-        // into nothing :)
-        let mut previous_table = vec![0; self.spring_layout.len() + 1];
-        previous_table[0] = 1;
+        self.count_arrangements_with(&mut Scratch::new())
+    }
+
+    /// Count the arrangements using the given buffers rather than fresh
+    /// ones, which saves a lot of allocating when counting many records
+    ///
+    /// ```
+    /// # use aoc2023::day12::*;
+    /// let mut scratch = Scratch::new();
+    /// let counts = ["???.### 1,1,3", ".??..??...?##. 1,1,3"].map(|line| {
+    ///     let record = Record::parse(line, 5).expect("Should have been a valid record");
+    ///     record.count_arrangements_with(&mut scratch)
+    /// });
+    /// assert_eq!(counts, [1, 16384]);
+    /// ```
+    pub fn count_arrangements_with(&self, scratch: &mut Scratch) -> usize {
+        self.checked_count_arrangements_with(scratch).expect("Arrangement count overflowed")
+    }
+
+    /// Count the arrangements in any type that can hold them, panicking if
+    /// the count doesn't fit rather than quietly wrapping around
+    ///
+    /// ```
+    /// # use aoc2023::day12::*;
+    /// let record = Record::parse("?###???????? 3,2,1", 5).expect("Should have been a valid record");
+    /// assert_eq!(record.count_arrangements_as::<u128>(), 506250);
+    /// ```
+    pub fn count_arrangements_as<T: Count>(&self) -> T {
+        self.checked_count_arrangements().expect("Arrangement count overflowed")
+    }
+
+    /// Count the arrangements, or `None` if there are too many for `T`
+    ///
+    /// ```
+    /// # use aoc2023::day12::*;
+    /// let record = Record::parse("?? 1", 40).expect("Should have been a valid record");
+    /// assert_eq!(record.checked_count_arrangements::<u32>(), None);
+    /// assert!(record.checked_count_arrangements::<u128>().is_some());
+    /// ```
+    pub fn checked_count_arrangements<T: Count>(&self) -> Option<T> {
+        self.checked_count_arrangements_with(&mut Scratch::new())
+    }
+
+    /// Count the arrangements using the given buffers, or `None` if there
+    /// are too many for `T`. Only two rows of the table are kept at a time.
+    pub fn checked_count_arrangements_with<T: Count>(&self, scratch: &mut Scratch<T>) -> Option<T> {
+        let width = self.spring_layout.len() + 1;
+        self.fill_finishable(&mut scratch.live, &mut scratch.runs);
+
+        scratch.previous.clear();
+        scratch.previous.resize(width, T::zero());
+        self.fill_first_row(&mut scratch.previous, &scratch.live);
+
+        for group in 0..self.group_sizes.len() {
+            scratch.current.clear();
+            scratch.current.resize(width, T::zero());
+            self.fill_row(group, &scratch.previous, &mut scratch.current, &scratch.live)?;
+            std::mem::swap(&mut scratch.previous, &mut scratch.current);
+        }
+
+        // The final entry in the final row of the table is our answer
+        scratch.previous.last().cloned()
+    }
+
+    fn table(&self) -> Vec<Vec<usize>> {
+        self.table_as().expect("Arrangement count overflowed")
+    }
+
+    /// The whole counting table, built from fresh buffers. The entry at
+    /// `[n][m]` is how many ways there are to fit the first `n` groups into
+    /// the first `m` positions, so the last entry of the last row is the
+    /// number of arrangements. Every row is kept, rather than just the last
+    /// one, so that arrangements can be traced back through the table
+    /// afterwards. `None` if any entry doesn't fit in a `T`.
+    ///
+    /// ```
+    /// # use aoc2023::day12::*;
+    /// let record = Record::parse("???.### 1,1,3", 1).expect("Should have been a valid record");
+    /// let table = record.table_as::<usize>().expect("Should have fitted in a usize");
+    /// assert_eq!(table.len(), 4);
+    /// assert_eq!(table.last().and_then(|row| row.last()), Some(&1));
+    /// ```
+    pub fn table_as<T: Count>(&self) -> Option<Vec<Vec<T>>> {
+        let width = self.spring_layout.len() + 1;
+        let (mut live, mut runs) = (vec![], vec![]);
+        self.fill_finishable(&mut live, &mut runs);
+
+        let mut tables = Vec::with_capacity(self.group_sizes.len() + 1);
+        let mut first = vec![T::zero(); width];
+        self.fill_first_row(&mut first, &live);
+        tables.push(first);
+
+        for group in 0..self.group_sizes.len() {
+            let mut current = vec![T::zero(); width];
+            self.fill_row(group, &tables[group], &mut current, &live)?;
+            tables.push(current);
+        }
+
+        Some(tables)
+    }
+
+    /// The first row of the table is the number of ways we can have
+    /// zero springs in the first n positions. There's exactly one way
+    /// to have nothing until we hit the first known spring, at which point
+    /// there are zero ways
+    ///
+    /// The zeroth position is trivially 1 - there's one way to put nothing
+    /// into nothing :)
+    fn fill_first_row<T: Count>(&self, row: &mut [T], live: &[bool]) {
+        row[0] = T::one();
         for (i, _) in self.spring_layout.iter().take_while(|&&ch| ch != '#').enumerate() {
-            previous_table[i + 1] = 1;
-        }
-
-        for &group_size in &self.group_sizes {
-            // Setup a new table to track the number of ways to position
-            // the current group taking into account all of the previous
-            // groups
-            let mut current_table = vec![0; self.spring_layout.len() + 1];
-            let mut non_empty_spots = 0;
-
-            for (position, &ch) in self.spring_layout.iter().enumerate() {
-                // Did we just leave the last contiguous block of
-                // springs or potential springs?
-                if ch != '.' {
-                    non_empty_spots += 1;
-                } else {
-                    non_empty_spots = 0;
-                }
-
-                // If the current spot isn't known to be a spring, then we
-                // know we can fit at least as many permutations as we could as
-                // of the last position for the current grouping. In other words,
-                // if it isn't certain that we just moved onto a spring, we can do
-                // at least as good as we could in the last position.
-                if ch != '#' {
-                    current_table[position + 1] += current_table[position];
-                }
-
-                // If we found a section that can fit the current group and it's *not*
-                // an extension of the previous contiguous block, then the previous
-                // m-1 groupings can all be handled by regions preceding this one,
-                // so add those in as well
-                if non_empty_spots >= group_size && self.spring_layout[position - group_size] != '#' {
-                    current_table[position + 1] += previous_table[position - group_size];
-                }
+            if live[i + 1] {
+                row[i + 1] = T::one();
             }
+        }
+    }
+
+    /// Fill in the row of the table for the given group, which tracks the
+    /// number of ways to position it taking into account all of the
+    /// previous groups. The row should start out as all zeros.
+    ///
+    /// Entries that can't go on to become a full arrangement are left at
+    /// zero, which keeps every entry no bigger than the final answer. If
+    /// the answer fits in a `T`, then so does the whole table.
+    fn fill_row<T: Count>(&self, group: usize, previous: &[T], current: &mut [T], live: &[bool]) -> Option<()> {
+        let group_size = self.group_sizes[group];
+        let live = &live[(group + 1) * current.len()..(group + 2) * current.len()];
+        let mut non_empty_spots = 0;
+
+        for (position, &ch) in self.spring_layout.iter().enumerate() {
+            // Did we just leave the last contiguous block of
+            // springs or potential springs?
+            if ch != '.' {
+                non_empty_spots += 1;
+            } else {
+                non_empty_spots = 0;
+            }
+
+            if !live[position + 1] {
+                continue;
+            }
+
+            // If the current spot isn't known to be a spring, then we
+            // know we can fit at least as many permutations as we could as
+            // of the last position for the current grouping. In other words,
+            // if it isn't certain that we just moved onto a spring, we can do
+            // at least as good as we could in the last position.
+            if ch != '#' {
+                current[position + 1] = current[position + 1].checked_add(&current[position])?;
+            }
+
+            // If we found a section that can fit the current group and it's *not*
+            // an extension of the previous contiguous block, then the previous
+            // m-1 groupings can all be handled by regions preceding this one,
+            // so add those in as well
+            if non_empty_spots >= group_size && self.spring_layout[position - group_size] != '#' {
+                current[position + 1] = current[position + 1].checked_add(&previous[position - group_size])?;
+            }
+        }
 
-            previous_table = current_table;
+        Some(())
+    }
+
+    /// Whether the groups from `g` onwards can still be placed after the
+    /// first `p` positions, for every `g` and `p`, one row per group. Like
+    /// the counting table, each group takes the position in front of it as
+    /// its separator.
+    fn fill_finishable(&self, live: &mut Vec<bool>, runs: &mut Vec<usize>) {
+        let layout = &self.spring_layout;
+        let positions = layout.len();
+        let width = positions + 1;
+        let groups = self.group_sizes.len();
+
+        // How many springs or potential springs start at each position
+        runs.clear();
+        runs.resize(width, 0);
+        for p in (0..positions).rev() {
+            runs[p] = if layout[p] != '.' { runs[p + 1] + 1 } else { 0 };
+        }
+
+        live.clear();
+        live.resize((groups + 1) * width, false);
+        live[groups * width + positions] = true;
+        for p in (0..positions).rev() {
+            live[groups * width + p] = layout[p] != '#' && live[groups * width + p + 1];
         }
 
-        // The final entry in the final row of the table is our
-        // answer
-        previous_table[self.spring_layout.len()]
+        for (g, &size) in self.group_sizes.iter().enumerate().rev() {
+            for p in (0..positions).rev() {
+                let skipped = layout[p] != '#' && live[g * width + p + 1];
+                let placed = layout[p] != '#'
+                    && p + size < positions
+                    && runs[p + 1] >= size
+                    && live[(g + 1) * width + p + size + 1];
+                live[g * width + p] = skipped || placed;
+            }
+        }
+    }
+
+    /// The same record read backwards, which shares a table layout with
+    /// this one. An entry in its table counts the ways to fit the *last* few
+    /// groups into the *last* few positions of this record.
+    fn reversed(&self) -> Self {
+        let spring_layout = once('.').chain(self.spring_layout[1..].iter().rev().copied()).collect();
+        let group_sizes = self.group_sizes.iter().rev().copied().collect();
+        Self { spring_layout, group_sizes, trailing_operational: 0 }
+    }
+
+    /// Lazily list every concrete arrangement of the record, with each
+    /// unknown spring filled in. Only branches of the table that lead to at
+    /// least one arrangement are followed, so taking the first few is cheap
+    /// even when there are trillions of them.
+    ///
+    /// ```
+    /// # use aoc2023::day12::*;
+    /// let record = Record::parse("?#?? 2,1", 1).expect("Should have been a valid record");
+    /// assert_eq!(record.arrangements().collect::<Vec<_>>(), vec!["##.#"]);
+    ///
+    /// let record = Record::parse("???? 1", 5).expect("Should have been a valid record");
+    /// assert_eq!(record.arrangements().take(3).count(), 3);
+    /// ```
+    pub fn arrangements(&self) -> Arrangements<'_> {
+        let table = self.table();
+        let groups = self.group_sizes.len();
+        let positions = self.spring_layout.len();
+
+        let stack = if table[groups][positions] > 0 {
+            vec![(groups, positions, self.spring_layout.clone())]
+        } else {
+            vec![]
+        };
+
+        Arrangements { record: self, table, stack }
+    }
+
+    /// For every unknown spring, how many arrangements have it damaged and
+    /// how many have it operational
+    ///
+    /// ```
+    /// # use aoc2023::day12::*;
+    /// let record = Record::parse("??? 1", 1).expect("Should have been a valid record");
+    /// let counts = record.cell_counts();
+    /// assert_eq!(counts[0], CellCount { position: 0, damaged: 1, operational: 2 });
+    /// ```
+    pub fn cell_counts(&self) -> Vec<CellCount> {
+        let forward = self.table();
+        let backward = self.reversed().table();
+        let groups = self.group_sizes.len();
+        let positions = self.spring_layout.len();
+        let total = forward[groups][positions];
+
+        // A spring is operational in exactly the arrangements that fit some
+        // number of groups before it and all of the rest after it
+        self.spring_layout.iter().enumerate().skip(1)
+            .filter(|(_, &ch)| ch == '?')
+            .map(|(idx, _)| {
+                let operational = (0..=groups)
+                    .map(|g| forward[g][idx] * backward[groups - g][positions - idx])
+                    .sum::<usize>();
+
+                CellCount { position: idx - 1, damaged: total - operational, operational }
+            })
+            .collect()
+    }
+
+    /// The unknown springs that are the same in every arrangement, along
+    /// with what they must be. If there are no arrangements at all then
+    /// nothing is reported.
+    ///
+    /// ```
+    /// # use aoc2023::day12::*;
+    /// let record = Record::parse("???.### 1,1,3", 1).expect("Should have been a valid record");
+    /// assert_eq!(record.forced_cells(), vec![(0, '#'), (1, '.'), (2, '#')]);
+    /// ```
+    pub fn forced_cells(&self) -> Vec<(usize, char)> {
+        self.cell_counts().into_iter()
+            .filter_map(|count| match (count.damaged, count.operational) {
+                (0, 0) => None,
+                (_, 0) => Some((count.position, '#')),
+                (0, _) => Some((count.position, '.')),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Buffers for counting the arrangements of records, which can be reused
+/// from one record to the next
+#[derive(Debug)]
+pub struct Scratch<T = usize> {
+    previous: Vec<T>,
+    current: Vec<T>,
+    live: Vec<bool>,
+    runs: Vec<usize>,
+}
+
+impl<T> Scratch<T> {
+    pub fn new() -> Self {
+        Self { previous: vec![], current: vec![], live: vec![], runs: vec![] }
+    }
+}
+
+impl<T> Default for Scratch<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Count the arrangements of every record, in order, spreading the records
+/// across as many threads as are available. Each thread keeps a single
+/// [`Scratch`] for all of its records.
+///
+/// ```
+/// # use aoc2023::day12::*;
+/// let records = ["???.### 1,1,3", "?###???????? 3,2,1"]
+///     .map(|line| Record::parse(line, 5).expect("Should have been a valid record"));
+/// assert_eq!(count_all(&records), vec![1, 506250]);
+/// ```
+pub fn count_all(records: &[Record]) -> Vec<usize> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = records.len().div_ceil(threads).max(1);
+
+    std::thread::scope(|scope| {
+        let handles = records.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || {
+                let mut scratch = Scratch::new();
+                chunk.iter().map(|record| record.count_arrangements_with(&mut scratch)).collect::<Vec<_>>()
+            }))
+            .collect::<Vec<_>>();
+
+        handles.into_iter()
+            .flat_map(|handle| handle.join().expect("Counting thread should not have panicked"))
+            .collect()
+    })
+}
+
+/// Iterator over the concrete arrangements of a [`Record`]
+pub struct Arrangements<'a> {
+    record: &'a Record,
+    table: Vec<Vec<usize>>,
+    stack: Vec<(usize, usize, Vec<char>)>,
+}
+
+impl<'a> Iterator for Arrangements<'a> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let layout = &self.record.spring_layout;
+
+        // Each entry is a partial arrangement where the first `g` groups are
+        // still to be placed in the first `p` positions, and everything from
+        // `p` onwards has been filled in. Walking back through the table the
+        // same way it was built, every position either ends a group or is
+        // operational.
+        while let Some((g, p, mut cells)) = self.stack.pop() {
+            if g == 0 {
+                // The position before the first group might have been the
+                // padding at the very start, which leaves nothing to fill
+                cells[1..p.max(1)].fill('.');
+                let tail = once('.').cycle().take(self.record.trailing_operational);
+                return Some(cells[1..].iter().copied().chain(tail).collect());
+            }
+
+            let last = p - 1;
+            if layout[last] != '#' && self.table[g][last] > 0 {
+                let mut cells = cells.clone();
+                cells[last] = '.';
+                self.stack.push((g, last, cells));
+            }
+
+            let size = self.record.group_sizes[g - 1];
+            if last >= size
+                && layout[p - size..p].iter().all(|&ch| ch != '.')
+                && layout[last - size] != '#'
+                && self.table[g - 1][last - size] > 0
+            {
+                cells[p - size..p].fill('#');
+                cells[last - size] = '.';
+                self.stack.push((g - 1, last - size, cells));
+            }
+        }
+
+        None
     }
 }
 
 #[cfg(test)]
 mod answers {
-    use crate::prelude::strings::*;
     use super::*;
+    use num_bigint::BigUint;
+    use std::collections::HashSet;
     use test_case::test_case;
 
     const SAMPLE: &str = include_str!("./input/day12-sample.txt");
@@ -106,9 +517,140 @@ mod answers {
     #[test_case(PERSONAL, 5 => 10_861_030_975_833; "with personal data and 5 folds")]
     pub fn problem_1_and_2(input: &str, num_folds: usize) -> usize {
         input.map_lines(|line| {
-            let record = Record::parse(line, num_folds);
+            let record = Record::parse(line, num_folds).expect("Should have been a valid record");
             record.count_arrangements()
         })
         .sum()
     }
+
+    #[test_case(SAMPLE, 5 => 525152; "with sample data and 5 folds")]
+    #[test_case(PERSONAL, 5 => 10_861_030_975_833; "with personal data and 5 folds")]
+    pub fn problem_2_in_parallel(input: &str, num_folds: usize) -> usize {
+        let records = input.map_lines(|line| Record::parse(line, num_folds).expect("Should have been a valid record"))
+            .collect::<Vec<_>>();
+        let counts = count_all(&records);
+
+        // Reusing one scratch buffer for every record shouldn't change anything
+        let mut scratch = Scratch::new();
+        let sequential = records.iter().map(|record| record.count_arrangements_with(&mut scratch)).collect::<Vec<_>>();
+        assert_eq!(counts, sequential);
+
+        counts.into_iter().sum()
+    }
+
+    fn is_valid(arrangement: &str, line: &str, num_folds: usize) -> bool {
+        let (pattern, groups) = line.split_once(' ').expect("Bad input");
+        let pattern = once(pattern).cycle().take(num_folds).join("?");
+        let groups = once(groups).cycle().take(num_folds).join(",");
+
+        let matches = arrangement.len() == pattern.len()
+            && arrangement.chars().zip(pattern.chars()).all(|(a, p)| p == '?' || a == p);
+        let found = arrangement.split('.')
+            .filter(|run| !run.is_empty())
+            .map(|run| run.len().to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        matches && found == groups
+    }
+
+    #[test_case(SAMPLE, 1; "with sample data")]
+    #[test_case(PERSONAL, 1; "with personal data")]
+    pub fn enumerates_every_arrangement(input: &str, num_folds: usize) {
+        for line in input.lines() {
+            let record = Record::parse(line, num_folds).expect("Should have been a valid record");
+            let all = record.arrangements().collect::<Vec<_>>();
+            let unique = all.iter().collect::<HashSet<_>>();
+
+            assert_eq!(all.len(), record.count_arrangements());
+            assert_eq!(unique.len(), all.len());
+            assert!(all.iter().all(|a| is_valid(a, line, num_folds)), "{line}");
+
+            // The marginal counts should agree with the brute force ones
+            for count in record.cell_counts() {
+                let damaged = all.iter().filter(|a| a.as_bytes()[count.position] == b'#').count();
+                assert_eq!((count.damaged, count.operational), (damaged, all.len() - damaged), "{line}");
+            }
+        }
+    }
+
+    #[test_case(SAMPLE; "with sample data")]
+    #[test_case(PERSONAL; "with personal data")]
+    pub fn enumerates_lazily_when_folded(input: &str) {
+        for line in input.lines().take(50) {
+            let record = Record::parse(line, 5).expect("Should have been a valid record");
+            let first = record.arrangements().take(10).collect::<Vec<_>>();
+
+            assert_eq!(first.len(), record.count_arrangements().min(10));
+            assert!(first.iter().all(|a| is_valid(a, line, 5)), "{line}");
+
+            let total = record.count_arrangements();
+            assert!(record.cell_counts().iter().all(|c| c.damaged + c.operational == total));
+        }
+    }
+
+    #[test_case("???.### 1,1,3" => vec![(0, '#'), (1, '.'), (2, '#')]; "with one arrangement")]
+    #[test_case("?###???????? 3,2,1" => vec![(0, '.'), (4, '.')]; "with a known group at the start")]
+    #[test_case(".??..??...?##. 1,1,3" => vec![(10, '#')]; "with a group one spring short")]
+    #[test_case("??? 4" => vec![]; "with no arrangements")]
+    pub fn forced(line: &str) -> Vec<(usize, char)> {
+        Record::parse(line, 1).expect("Should have been a valid record").forced_cells()
+    }
+
+    #[test]
+    pub fn counts_agree_across_types() {
+        for line in PERSONAL.lines() {
+            let record = Record::parse(line, 5).expect("Should have been a valid record");
+            let small = record.count_arrangements_as::<u64>();
+
+            assert_eq!(record.count_arrangements_as::<u128>(), small as u128);
+            assert_eq!(record.count_arrangements_as::<BigUint>(), BigUint::from(small));
+        }
+    }
+
+    #[test]
+    pub fn heavily_unfolded() {
+        // Every '?' pair holds exactly one damaged spring, with the joining
+        // '?'s between them, so this is choosing 70 of 140 slots in disguise
+        let record = Record::parse("?? 1", 70).expect("Should have been a valid record");
+
+        let mut pascal = vec![BigUint::one()];
+        for _ in 0..140 {
+            let mut next = vec![BigUint::one(); pascal.len() + 1];
+            for (idx, pair) in pascal.windows(2).enumerate() {
+                next[idx + 1] = &pair[0] + &pair[1];
+            }
+            pascal = next;
+        }
+
+        assert_eq!(record.count_arrangements_as::<BigUint>(), pascal[70]);
+        assert_eq!(record.checked_count_arrangements::<u128>(), None);
+        assert_eq!(record.checked_count_arrangements::<u64>(), None);
+    }
+
+    #[test_case(33 => matches Some(_); "just within a u64")]
+    #[test_case(34 => None; "just past a u64")]
+    pub fn checked_overflow(num_folds: usize) -> Option<u64> {
+        Record::parse("?? 1", num_folds).expect("Should have been a valid record").checked_count_arrangements()
+    }
+
+    #[test_case("???.### 1,1,3", 0 => ParseError::NoFolds; "without folds")]
+    #[test_case("???.###", 1 => ParseError::MissingGroups; "without groups")]
+    #[test_case("??x.### 1,1,3", 1 => ParseError::UnknownSpring('x'); "with an unknown spring")]
+    #[test_case("???.### 1,,3", 1 => ParseError::BadGroupSize("".into()); "with an empty group")]
+    #[test_case("???.### 1,0,3", 1 => ParseError::BadGroupSize("0".into()); "with a zero sized group")]
+    #[test_case("???.### 1,1,3", usize::MAX / 4 => ParseError::TooManyFolds; "with too many folds")]
+    #[test_case("???.### 1,1,3", 10_000 => ParseError::TooManyFolds; "with too many springs once unfolded")]
+    #[test_case("? 1,1,1,1,1,1,1,1", 10_000 => ParseError::TooManyFolds; "with too many groups once unfolded")]
+    #[test_case("? 1,1,1", usize::MAX / 2 => ParseError::TooManyFolds; "with groups that overflow once unfolded")]
+    pub fn parse_errors(line: &str, num_folds: usize) -> ParseError {
+        Record::parse(line, num_folds).expect_err("Should have been an invalid record")
+    }
+
+    #[test]
+    pub fn without_groups() {
+        let record = Record::parse("?.? ", 2).expect("Should have been a valid record");
+        assert_eq!(record.count_arrangements(), 1);
+        assert_eq!(record.arrangements().collect::<Vec<_>>(), vec![".......".to_owned()]);
+    }
 }
@@ -1,5 +1,329 @@
 //! Day 13 - Point of Incidence
 
+use std::fmt::Display;
+
+/// A row or column of a pattern as a bitset, where rocks are set. Unlike
+/// the packed `u32`s from [`parse_image`], there's no limit on its length.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Bits {
+    words: Vec<u64>,
+}
+
+impl Bits {
+    /// All clear, with room for at least `len` bits
+    pub fn with_len(len: usize) -> Self {
+        Self { words: vec![0; len.div_ceil(64)] }
+    }
+
+    pub fn set(&mut self, idx: usize) {
+        self.words[idx / 64] |= 1 << (idx % 64);
+    }
+
+    pub fn contains(&self, idx: usize) -> bool {
+        self.words.get(idx / 64).is_some_and(|word| word & (1 << (idx % 64)) != 0)
+    }
+
+    /// The first `len` bits in the opposite order
+    pub fn reversed(&self, len: usize) -> Self {
+        let mut reversed = Self::with_len(len);
+        for idx in (0..len).filter(|&idx| self.contains(len - idx - 1)) {
+            reversed.set(idx);
+        }
+        reversed
+    }
+
+    /// How many bits differ between the two
+    pub fn distance(&self, other: &Self) -> usize {
+        self.words.iter().zip(&other.words).map(|(a, b)| (a ^ b).count_ones() as usize).sum()
+    }
+
+    /// The index of every bit that differs between the two
+    pub fn differences<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = usize> + 'a {
+        self.words.iter().zip(&other.words).enumerate().flat_map(|(idx, (a, b))| {
+            let mut diff = a ^ b;
+            std::iter::from_fn(move || {
+                (diff != 0).then(|| {
+                    let bit = diff.trailing_zeros() as usize;
+                    diff &= diff - 1;
+                    idx * 64 + bit
+                })
+            })
+        })
+    }
+}
+
+/// Which way a line of reflection runs
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Axis {
+    /// Between two rows
+    Horizontal,
+    /// Between two columns
+    Vertical,
+}
+
+/// Ways that a pattern can look the same after being moved
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Symmetry {
+    /// Reflected about a line between rows or columns
+    Mirror { axis: Axis, index: usize },
+    /// Turned 180° about its centre
+    Rotational,
+    /// Flipped along the diagonal from the top left, e.g. transposed. Only
+    /// square patterns can have this.
+    Diagonal,
+}
+
+/// A line of reflection in a pattern
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reflection {
+    pub axis: Axis,
+    /// How many rows are above the line, or columns to the left of it
+    pub index: usize,
+    /// The cells that have to change for the reflection to be perfect, as
+    /// (row, col). Only the cell above or to the left of the line is given
+    /// for each mismatched pair, although fixing its mirror image would do
+    /// just as well.
+    pub smudges: Vec<(usize, usize)>,
+}
+
+impl Reflection {
+    /// The number used to summarise the notes - rows above are worth 100
+    /// each, columns to the left are worth 1
+    pub fn summary(&self) -> usize {
+        match self.axis {
+            Axis::Horizontal => self.index * 100,
+            Axis::Vertical => self.index,
+        }
+    }
+}
+
+/// A pattern had more than one line of reflection with the requested number
+/// of smudges, so there's no telling which one was meant
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AmbiguousReflection(pub Vec<Reflection>);
+
+impl Display for AmbiguousReflection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "found {} possible reflections", self.0.len())
+    }
+}
+
+impl std::error::Error for AmbiguousReflection {}
+
+/// Ways that the puzzle input can fail to describe a pattern
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// There were no rows at all
+    Empty,
+    /// A row was a different length to the first one
+    Ragged { row: usize },
+    /// Something other than '.' or '#'
+    UnknownTile(char),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty pattern"),
+            Self::Ragged { row } => write!(f, "row {row} has a different length to the first"),
+            Self::UnknownTile(ch) => write!(f, "unknown tile {ch:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A pattern of ash and rocks, stored both by row and by column so that
+/// either can be compared a whole line at a time
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pattern {
+    rows: Vec<Bits>,
+    cols: Vec<Bits>,
+}
+
+impl Pattern {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let lines = input.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>();
+        let nrows = lines.len();
+        let ncols = lines.first().ok_or(ParseError::Empty)?.len();
+
+        let mut rows = vec![Bits::with_len(ncols); nrows];
+        let mut cols = vec![Bits::with_len(nrows); ncols];
+        for (y, line) in lines.iter().enumerate() {
+            if line.len() != ncols {
+                return Err(ParseError::Ragged { row: y });
+            }
+
+            for (x, ch) in line.chars().enumerate() {
+                match ch {
+                    '#' => {
+                        rows[y].set(x);
+                        cols[x].set(y);
+                    },
+                    '.' => {},
+                    other => return Err(ParseError::UnknownTile(other)),
+                }
+            }
+        }
+
+        Ok(Self { rows, cols })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols.len()
+    }
+
+    pub fn is_rock(&self, row: usize, col: usize) -> bool {
+        self.rows.get(row).is_some_and(|bits| bits.contains(col))
+    }
+
+    /// Every line of reflection with exactly the given number of smudges,
+    /// horizontal ones first
+    pub fn reflections(&self, smudges: usize) -> Vec<Reflection> {
+        let horizontal = Self::lines_of(&self.rows, smudges)
+            .map(|(index, cells)| Reflection { axis: Axis::Horizontal, index, smudges: cells });
+        let vertical = Self::lines_of(&self.cols, smudges)
+            .map(|(index, cells)| Reflection {
+                axis: Axis::Vertical,
+                index,
+                smudges: cells.into_iter().map(|(col, row)| (row, col)).collect(),
+            });
+
+        horizontal.chain(vertical).collect()
+    }
+
+    /// The one line of reflection with exactly the given number of smudges,
+    /// if there is one
+    ///
+    /// ```
+    /// # use aoc2023::day13::*;
+    /// let pattern = Pattern::parse("#.##..##.\n..#.##.#.\n##......#\n##......#\n..#.##.#.\n..##..##.\n#.#.##.#.")
+    ///     .expect("Should have been a valid pattern");
+    ///
+    /// let clean = pattern.find_reflection(0).expect("Should have been unambiguous").expect("Should have reflected");
+    /// assert_eq!((clean.axis, clean.index), (Axis::Vertical, 5));
+    ///
+    /// let smudged = pattern.find_reflection(1).expect("Should have been unambiguous").expect("Should have reflected");
+    /// assert_eq!(smudged, Reflection { axis: Axis::Horizontal, index: 3, smudges: vec![(0, 0)] });
+    /// ```
+    pub fn find_reflection(&self, smudges: usize) -> Result<Option<Reflection>, AmbiguousReflection> {
+        let mut found = self.reflections(smudges);
+        match found.len() {
+            0 => Ok(None),
+            1 => Ok(found.pop()),
+            _ => Err(AmbiguousReflection(found)),
+        }
+    }
+
+    /// Every kind of symmetry the pattern has with exactly the given number
+    /// of smudges, along with where those smudges are. Lines of reflection
+    /// come first, in the same order as [`Pattern::reflections`].
+    ///
+    /// ```
+    /// # use aoc2023::day13::*;
+    /// let pattern = Pattern::parse("#..\n.#.\n..#").expect("Should have been a valid pattern");
+    /// let kinds = pattern.symmetries(0).into_iter().map(|(kind, _)| kind).collect::<Vec<_>>();
+    /// assert_eq!(kinds, vec![Symmetry::Rotational, Symmetry::Diagonal]);
+    /// ```
+    pub fn symmetries(&self, smudges: usize) -> Vec<(Symmetry, Vec<(usize, usize)>)> {
+        let mirrors = self.reflections(smudges).into_iter()
+            .map(|reflection| (Symmetry::Mirror { axis: reflection.axis, index: reflection.index }, reflection.smudges));
+        let others = [Symmetry::Rotational, Symmetry::Diagonal].into_iter()
+            .filter_map(|kind| self.smudges_for(kind).map(|cells| (kind, cells)))
+            .filter(|(_, cells)| cells.len() == smudges);
+
+        mirrors.chain(others).collect()
+    }
+
+    /// Where the smudges are that stop the pattern from having the given
+    /// symmetry, as (row, col). Each mismatched pair of cells is one smudge,
+    /// and only the first of the pair in reading order is given. Returns
+    /// `None` if the pattern can't have that symmetry at all, e.g. a line of
+    /// reflection that's off the edge or a diagonal in a pattern that isn't
+    /// square.
+    ///
+    /// ```
+    /// # use aoc2023::day13::*;
+    /// let pattern = Pattern::parse("##.\n.#.\n..#").expect("Should have been a valid pattern");
+    /// assert_eq!(pattern.smudges_for(Symmetry::Diagonal), Some(vec![(0, 1)]));
+    /// assert_eq!(pattern.smudges_for(Symmetry::Rotational), Some(vec![(0, 1)]));
+    /// ```
+    pub fn smudges_for(&self, symmetry: Symmetry) -> Option<Vec<(usize, usize)>> {
+        let (nrows, ncols) = (self.rows(), self.cols());
+
+        match symmetry {
+            Symmetry::Mirror { axis: Axis::Horizontal, index } => {
+                (1..nrows).contains(&index).then(|| Self::mismatches(&self.rows, index).collect())
+            },
+            Symmetry::Mirror { axis: Axis::Vertical, index } => {
+                (1..ncols).contains(&index).then(|| {
+                    let mut cells = Self::mismatches(&self.cols, index).map(|(col, row)| (row, col)).collect::<Vec<_>>();
+                    cells.sort_unstable();
+                    cells
+                })
+            },
+            Symmetry::Rotational => {
+                // Each row has to match the one opposite it read backwards.
+                // The middle row, if there is one, is opposite itself, so
+                // only half of it counts.
+                let cells = (0..nrows.div_ceil(2))
+                    .flat_map(|row| {
+                        let opposite = self.rows[nrows - row - 1].reversed(ncols);
+                        self.rows[row].differences(&opposite)
+                            .filter(move |&col| row < nrows - row - 1 || col < ncols - col - 1)
+                            .map(move |col| (row, col))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+
+                Some(cells)
+            },
+            Symmetry::Diagonal => {
+                // Each row has to match the column with the same index,
+                // and everything above the diagonal has a partner below it
+                (nrows == ncols).then(|| {
+                    (0..nrows)
+                        .flat_map(|row| {
+                            self.rows[row].differences(&self.cols[row])
+                                .filter(move |&col| col > row)
+                                .map(move |col| (row, col))
+                        })
+                        .collect()
+                })
+            },
+        }
+    }
+
+    /// Lines of reflection between the given lines, as the number of lines
+    /// before it along with the (line, offset) of every mismatch
+    fn lines_of(lines: &[Bits], smudges: usize) -> impl Iterator<Item = (usize, Vec<(usize, usize)>)> + '_ {
+        (1..lines.len()).filter_map(move |index| {
+            // Counting is cheap, so only go looking for where the smudges
+            // are once we know this is the right line
+            let mut total = 0;
+            for (before, after) in (0..index).rev().zip(index..lines.len()) {
+                total += lines[before].distance(&lines[after]);
+                if total > smudges {
+                    return None;
+                }
+            }
+
+            (total == smudges).then(|| (index, Self::mismatches(lines, index).collect()))
+        })
+    }
+
+    /// The (line, offset) of every mismatch either side of a line of
+    /// reflection, on the side before it
+    fn mismatches(lines: &[Bits], index: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..index).rev().zip(index..lines.len())
+            .flat_map(|(before, after)| lines[before].differences(&lines[after]).map(move |offset| (before, offset)))
+    }
+}
+
 pub fn parse_image(input: &str) -> (Vec<u32>, Vec<u32>) {
     let lines = input.lines().collect::<Vec<_>>();
     let nrows = lines.len();
@@ -19,7 +343,8 @@ pub fn parse_image(input: &str) -> (Vec<u32>, Vec<u32>) {
    (rows, cols)
 }
 
-// Find a reflection that contains exactly _tolerance_ errors
+// Find a reflection that contains exactly _tolerance_ errors. Patterns wider or
+// taller than 32 don't fit, so prefer a [`Pattern`] for anything new.
 pub fn find_incidence(values: &[u32], tolerance: u32) -> usize {
     for (i, _) in values.windows(2).enumerate() {
         let q = values[..=i].iter().rev();
@@ -52,4 +377,102 @@ mod answers {
         })
         .sum()
     }
+
+    #[test_case(SAMPLE, 0 => 405; "with sample data")]
+    #[test_case(PERSONAL, 0 => 37113; "with personal data")]
+    #[test_case(SAMPLE, 1 => 400; "with sample data and smudges")]
+    #[test_case(PERSONAL, 1 => 30449; "with personal data and smudges")]
+    pub fn problem_1_and_2_with_patterns(input: &str, smudges: usize) -> usize {
+        input.split("\n\n").map(|image| {
+            let pattern = Pattern::parse(image).expect("Should have been a valid pattern");
+            let reflection = pattern.find_reflection(smudges)
+                .expect("Should have had only one reflection")
+                .expect("Should have had a reflection");
+
+            // Fixing the smudges should leave a perfect reflection
+            assert_eq!(reflection.smudges.len(), smudges);
+            reflection.summary()
+        })
+        .sum()
+    }
+
+    #[test]
+    pub fn smudge_locations() {
+        let patterns = SAMPLE.split("\n\n")
+            .map(|image| Pattern::parse(image).expect("Should have been a valid pattern"))
+            .collect::<Vec<_>>();
+
+        let smudges = patterns.iter()
+            .map(|pattern| pattern.find_reflection(1).expect("Should have been unambiguous").expect("Should have reflected"))
+            .map(|reflection| reflection.smudges)
+            .collect::<Vec<_>>();
+
+        assert_eq!(smudges, vec![vec![(0, 0)], vec![(0, 4)]]);
+    }
+
+    #[test]
+    pub fn wider_than_a_word() {
+        // 70 columns, mirrored about the middle, with one smudge far off to
+        // the right where a u32 couldn't see it
+        let mut rows = ["#..##.#...#.##.#.....##.#.#...###.#", ".#.####..#.##...#..#.#.##..#.#.#...", "##...#.#..###.#...#..#.####.#..##.."]
+            .map(|half| format!("{half}{}", half.chars().rev().collect::<String>()));
+        rows[0].replace_range(50..51, "#");
+        let pattern = Pattern::parse(&rows.join("\n")).expect("Should have been a valid pattern");
+
+        assert_eq!(pattern.cols(), 70);
+        assert_eq!(pattern.find_reflection(0), Ok(None));
+        assert_eq!(pattern.find_reflection(1), Ok(Some(Reflection { axis: Axis::Vertical, index: 35, smudges: vec![(0, 19)] })));
+    }
+
+    #[test]
+    pub fn ambiguous_reflections() {
+        let pattern = Pattern::parse("#..#\n#..#").expect("Should have been a valid pattern");
+        let found = pattern.find_reflection(0).expect_err("Should have been ambiguous");
+
+        assert_eq!(found.0.iter().map(|r| (r.axis, r.index)).collect::<Vec<_>>(), vec![(Axis::Horizontal, 1), (Axis::Vertical, 2)]);
+        assert_eq!(Pattern::parse("#.\n..").map(|p| p.find_reflection(0)), Ok(Ok(None)));
+    }
+
+    #[test_case("" => ParseError::Empty; "when empty")]
+    #[test_case("#.\n#" => ParseError::Ragged { row: 1 }; "with ragged rows")]
+    #[test_case("#.\nO." => ParseError::UnknownTile('O'); "with an unknown tile")]
+    pub fn parse_errors(input: &str) -> ParseError {
+        Pattern::parse(input).expect_err("Should have been invalid")
+    }
+
+    #[test_case("#..\n.#.\n..#" => vec![Symmetry::Rotational, Symmetry::Diagonal]; "with both diagonal kinds")]
+    #[test_case("#.\n##\n.#" => vec![Symmetry::Rotational]; "with a rotation that isn't square")]
+    #[test_case("##\n#." => vec![Symmetry::Diagonal]; "with a diagonal only")]
+    #[test_case("#.#\n#.#" => vec![
+        Symmetry::Mirror { axis: Axis::Horizontal, index: 1 },
+        Symmetry::Rotational,
+    ]; "with a mirror and a rotation")]
+    pub fn symmetries(input: &str) -> Vec<Symmetry> {
+        let pattern = Pattern::parse(input).expect("Should have been a valid pattern");
+        pattern.symmetries(0).into_iter().map(|(kind, _)| kind).collect()
+    }
+
+    #[test_case("##.\n...\n..#", Symmetry::Rotational => Some(vec![(0, 1)]); "rotation off by one")]
+    #[test_case("#.\n..\n..", Symmetry::Rotational => Some(vec![(0, 0)]); "rotation with a corner")]
+    #[test_case("...\n..#\n...", Symmetry::Rotational => Some(vec![(1, 0)]); "rotation in the middle row")]
+    #[test_case(".#.\n...\n#..", Symmetry::Diagonal => Some(vec![(0, 1), (0, 2)]); "diagonal off by two")]
+    #[test_case("#.\n..\n..", Symmetry::Diagonal => None; "diagonal when not square")]
+    #[test_case("#.\n..", Symmetry::Mirror { axis: Axis::Vertical, index: 1 } => Some(vec![(0, 0)]); "mirror")]
+    #[test_case("#.\n..", Symmetry::Mirror { axis: Axis::Vertical, index: 2 } => None; "mirror off the edge")]
+    pub fn smudges_for(input: &str, symmetry: Symmetry) -> Option<Vec<(usize, usize)>> {
+        Pattern::parse(input).expect("Should have been a valid pattern").smudges_for(symmetry)
+    }
+
+    #[test]
+    pub fn symmetries_with_smudges() {
+        // One smudge away from every kind of symmetry, in different places
+        let pattern = Pattern::parse("#..\n##.\n..#").expect("Should have been a valid pattern");
+        assert_eq!(pattern.symmetries(1), vec![
+            (Symmetry::Mirror { axis: Axis::Horizontal, index: 1 }, vec![(0, 1)]),
+            (Symmetry::Mirror { axis: Axis::Vertical, index: 1 }, vec![(0, 0)]),
+            (Symmetry::Rotational, vec![(1, 0)]),
+            (Symmetry::Diagonal, vec![(0, 1)]),
+        ]);
+        assert_eq!(pattern.symmetries(0), vec![]);
+    }
 }
@@ -1,28 +1,104 @@
 //! Day 14 - Parabolic Reflector Dish
 
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display};
 
+/// Which way to tilt the platform, e.g. which edge the rocks roll towards
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+/// The platform as two bitsets, one for the rounded rocks and one for the
+/// cube-shaped ones. Each row is packed into as many words as it needs, with
+/// bit `c` of a row being column `c`. Any bits past the last column are
+/// filled with cubes, so rocks rolling east stop at the edge by themselves.
 #[derive(Clone, Eq, PartialEq, Hash)]
 pub struct Platform {
-    data: Vec<Vec<char>>,
+    rows: usize,
+    cols: usize,
+    stride: usize,
+    rounded: Vec<u64>,
+    cubes: Vec<u64>,
 }
 
 impl Platform {
     pub fn parse(input: &str) -> Self {
-        Self { data: input.lines().map(|line| line.chars().collect()).collect() }
+        let lines = input.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>();
+        let cols = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        let mut platform = Self::with_size(lines.len(), cols);
+
+        for (row, line) in lines.iter().enumerate() {
+            for (col, ch) in line.chars().enumerate() {
+                platform.set(row, col, ch);
+            }
+        }
+
+        platform
     }
 
+    /// A platform the same size as this one with nothing on it
     pub fn as_empty(&self) -> Self {
-        Self { data: vec![vec!['.'; self.data.len()]; self.data.len()] }
+        Self::with_size(self.rows, self.cols)
+    }
+
+    fn with_size(rows: usize, cols: usize) -> Self {
+        let stride = cols.div_ceil(64).max(1);
+
+        // Wall off everything past the last column
+        let mut padding = vec![0; stride];
+        for col in cols..stride * 64 {
+            padding[col / 64] |= 1 << (col % 64);
+        }
+
+        Self {
+            rows,
+            cols,
+            stride,
+            rounded: vec![0; rows * stride],
+            cubes: padding.repeat(rows),
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// What's at the given position, as it appears in the puzzle input
+    pub fn get(&self, row: usize, col: usize) -> char {
+        let (word, bit) = (row * self.stride + col / 64, 1 << (col % 64));
+        if self.rounded[word] & bit != 0 {
+            'O'
+        } else if self.cubes[word] & bit != 0 {
+            '#'
+        } else {
+            '.'
+        }
+    }
+
+    fn set(&mut self, row: usize, col: usize, ch: char) {
+        let (word, bit) = (row * self.stride + col / 64, 1 << (col % 64));
+        self.rounded[word] &= !bit;
+        self.cubes[word] &= !bit;
+        match ch {
+            'O' => self.rounded[word] |= bit,
+            '#' => self.cubes[word] |= bit,
+            _ => {},
+        }
     }
 
     pub fn rotate_right(&self) -> Self {
-        let mut next = self.as_empty();
-        let dim = self.data.len();
+        let mut next = Self::with_size(self.cols, self.rows);
 
-        for col in 0..dim {
-            for row in 0..dim {
-                next.data[col][dim-row-1] = self.data[row][col];
+        for col in 0..self.cols {
+            for row in 0..self.rows {
+                next.set(col, self.rows - row - 1, self.get(row, col));
             }
         }
 
@@ -30,53 +106,279 @@ impl Platform {
     }
 
     pub fn tilt_north(&self) -> Self {
-        let mut next = self.as_empty();
-        let dim = self.data.len();
-
-        for col in 0..dim {
-            for row in 0..dim {
-                match self.data[row][col] {
-                    '.' => { 
-                        // Just skip these
-                    },
-                    '#' => {
-                        next.data[row][col] = '#';
-                    },
-                    'O' => {
-                        let mut new_row = row;
-                        for check in (0..row).rev() {
-                            if next.data[check][col] != '.' {
-                                break;
-                            }
-
-                            new_row -= 1;
-                        }
-
-                        next.data[new_row][col] = 'O';
-                    }
-                    _ => {}
+        let mut next = self.clone();
+        next.tilt(Direction::North);
+        next
+    }
+
+    /// Tilt the platform so that every rounded rock rolls as far as it can
+    /// towards the given edge
+    ///
+    /// ```
+    /// # use aoc2023::day14::*;
+    /// let mut platform = Platform::parse(".O.#O\nO..#.");
+    /// platform.tilt(Direction::East);
+    /// assert_eq!(platform.to_string(), "..O#O\n..O#.\n\n");
+    /// ```
+    pub fn tilt(&mut self, direction: Direction) {
+        // Every step moves each rock that can move by one place, all at
+        // once, a whole word of columns at a time. Rocks only ever move into
+        // empty space, so repeating until nothing moves leaves them settled.
+        match direction {
+            Direction::North | Direction::South => while self.step_rows(direction) {},
+            Direction::East | Direction::West => {
+                for row in 0..self.rows {
+                    while self.step_within_row(row, direction) {}
                 }
+            },
+        }
+    }
+
+    /// Move rocks one row north or south
+    fn step_rows(&mut self, direction: Direction) -> bool {
+        let stride = self.stride;
+        let mut moved = false;
+
+        for row in 1..self.rows {
+            let (from, to) = match direction {
+                Direction::North => (row, row - 1),
+                _ => (self.rows - row - 1, self.rows - row),
+            };
+
+            for word in 0..stride {
+                let (from, to) = (from * stride + word, to * stride + word);
+                let moving = self.rounded[from] & !(self.rounded[to] | self.cubes[to]);
+                self.rounded[from] &= !moving;
+                self.rounded[to] |= moving;
+                moved |= moving != 0;
             }
         }
 
-        next
+        moved
+    }
+
+    /// Move rocks in a single row one column east or west. Bits carry
+    /// between the words of a row, so the words are visited in the
+    /// opposite order to the way the rocks are moving, to make sure each
+    /// word is read before it's updated.
+    fn step_within_row(&mut self, row: usize, direction: Direction) -> bool {
+        let words = &mut self.rounded[row * self.stride..(row + 1) * self.stride];
+        let cubes = &self.cubes[row * self.stride..(row + 1) * self.stride];
+        let mut moved = false;
+
+        // The rocks that arrived in the previously visited word
+        let mut previous = 0;
+
+        if direction == Direction::East {
+            for idx in (0..words.len()).rev() {
+                let carried = if idx > 0 { words[idx - 1] >> 63 } else { 0 };
+                let arriving = (words[idx] << 1 | carried) & !(words[idx] | cubes[idx]);
+                let departing = arriving >> 1 | previous << 63;
+                words[idx] = (words[idx] & !departing) | arriving;
+                previous = arriving;
+                moved |= arriving != 0;
+            }
+        } else {
+            for idx in 0..words.len() {
+                let carried = words.get(idx + 1).map_or(0, |word| word << 63);
+                let arriving = (words[idx] >> 1 | carried) & !(words[idx] | cubes[idx]);
+                let departing = arriving << 1 | previous >> 63;
+                words[idx] = (words[idx] & !departing) | arriving;
+                previous = arriving;
+                moved |= arriving != 0;
+            }
+        }
+
+        moved
+    }
+
+    /// Tilt north, west, south and then east
+    pub fn spin_cycle(&mut self) {
+        for direction in Script::SPIN_CYCLE {
+            self.tilt(direction);
+        }
+    }
+
+    /// Run the given number of spin cycles. The platform soon settles into
+    /// a loop, so once it's seen the same arrangement twice it skips
+    /// straight to the end.
+    ///
+    /// ```
+    /// # use aoc2023::day14::*;
+    /// let mut platform = Platform::parse("O.#\n.O.\n#.O");
+    /// platform.spin_cycles(1_000_000_000);
+    /// assert_eq!(platform.to_string(), ".O#\n..O\n#.O\n\n");
+    /// ```
+    pub fn spin_cycles(&mut self, cycles: usize) {
+        self.repeat(&Script::SPIN_CYCLE, cycles);
+    }
+
+    /// Tilt the platform in each of the given directions in turn, that
+    /// many times over, skipping ahead once it starts to loop
+    pub fn repeat(&mut self, tilts: &[Direction], times: usize) {
+        let mut seen = HashMap::new();
+        let mut history = vec![];
+
+        for done in 0..times {
+            if let Some(start) = seen.insert(self.clone(), done) {
+                let period = done - start;
+                *self = history.swap_remove(start + (times - start) % period);
+                return;
+            }
+
+            history.push(self.clone());
+            for &direction in tilts {
+                self.tilt(direction);
+            }
+        }
+    }
+
+    /// Run every stage of a script in order
+    ///
+    /// ```
+    /// # use aoc2023::day14::*;
+    /// let script = Script::parse("N W S E x1000000000").expect("Should have been a valid script");
+    /// let mut platform = Platform::parse("O.#\n.O.\n#.O");
+    /// platform.run(&script);
+    /// assert_eq!(platform.to_string(), ".O#\n..O\n#.O\n\n");
+    /// ```
+    pub fn run(&mut self, script: &Script) {
+        for (tilts, times) in &script.stages {
+            self.repeat(tilts, *times);
+        }
     }
 
     pub fn calc_weight(&self) -> usize {
-        self.data.iter().rev().enumerate().map(|(idx, row)| {
-            let num_stones = row.iter().filter(|&ch| ch == &'O').count();
-            num_stones * (idx + 1)
-        })
-        .sum()
+        self.rows_of_rocks().map(|(row, count)| count * (self.rows - row)).sum()
+    }
+
+    /// The total load on the support beams along the given edge. Each rock
+    /// counts for how many rows or columns it is from the opposite edge.
+    ///
+    /// ```
+    /// # use aoc2023::day14::*;
+    /// let platform = Platform::parse("O.#\n..O");
+    /// assert_eq!(platform.load(Direction::North), 3);
+    /// assert_eq!(platform.load(Direction::East), 4);
+    /// assert_eq!(platform.load(Direction::South), 3);
+    /// assert_eq!(platform.load(Direction::West), 4);
+    /// ```
+    pub fn load(&self, edge: Direction) -> usize {
+        match edge {
+            Direction::North => self.calc_weight(),
+            Direction::South => self.rows_of_rocks().map(|(row, count)| count * (row + 1)).sum(),
+            Direction::East | Direction::West => {
+                (0..self.rows)
+                    .flat_map(|row| (0..self.cols).filter(move |&col| self.get(row, col) == 'O'))
+                    .map(|col| if edge == Direction::East { col + 1 } else { self.cols - col })
+                    .sum()
+            },
+        }
+    }
+
+    /// How many rounded rocks there are in each row
+    fn rows_of_rocks(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.rounded.chunks(self.stride)
+            .map(|row| row.iter().map(|word| word.count_ones() as usize).sum())
+            .enumerate()
+    }
+}
+
+/// Ways that a script can fail to make sense
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScriptError {
+    /// Something other than a direction or a repeat count
+    UnknownOperation(String),
+    /// A repeat count that wasn't a number
+    BadRepeat(String),
+    /// A repeat count with no directions before it to repeat
+    NothingToRepeat,
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownOperation(op) => write!(f, "unknown operation {op:?}"),
+            Self::BadRepeat(op) => write!(f, "bad repeat count {op:?}"),
+            Self::NothingToRepeat => write!(f, "repeat count with nothing to repeat"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// A schedule of tilts, written as directions (`N`, `E`, `S` or `W`) each
+/// followed by an optional repeat count such as `x1000000000`. A repeat
+/// applies to every direction since the previous one, so `"N W S E x3 N"`
+/// spins the platform three times and then tilts it north once more.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Script {
+    stages: Vec<(Vec<Direction>, usize)>,
+}
+
+impl Script {
+    /// One full spin cycle
+    pub const SPIN_CYCLE: [Direction; 4] = [Direction::North, Direction::West, Direction::South, Direction::East];
+
+    pub fn parse(input: &str) -> Result<Self, ScriptError> {
+        let mut stages = vec![];
+        let mut tilts = vec![];
+
+        for op in input.split_whitespace() {
+            let direction = match op {
+                "N" => Direction::North,
+                "E" => Direction::East,
+                "S" => Direction::South,
+                "W" => Direction::West,
+                _ => {
+                    let times = op.strip_prefix('x').ok_or_else(|| ScriptError::UnknownOperation(op.to_owned()))?;
+                    let times = times.parse().map_err(|_| ScriptError::BadRepeat(op.to_owned()))?;
+                    if tilts.is_empty() {
+                        return Err(ScriptError::NothingToRepeat);
+                    }
+
+                    stages.push((std::mem::take(&mut tilts), times));
+                    continue;
+                },
+            };
+
+            tilts.push(direction);
+        }
+
+        if !tilts.is_empty() {
+            stages.push((tilts, 1));
+        }
+
+        Ok(Self { stages })
+    }
+
+    /// The tilts in each stage, and how many times the stage is repeated
+    pub fn stages(&self) -> &[(Vec<Direction>, usize)] {
+        &self.stages
+    }
+}
+
+impl Display for Script {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ops = self.stages.iter().flat_map(|(tilts, times)| {
+            let tilts = tilts.iter().map(|direction| match direction {
+                Direction::North => "N".to_owned(),
+                Direction::East => "E".to_owned(),
+                Direction::South => "S".to_owned(),
+                Direction::West => "W".to_owned(),
+            });
+            tilts.chain((*times != 1).then(|| format!("x{times}")))
+        });
+
+        write!(f, "{}", ops.collect::<Vec<_>>().join(" "))
     }
 }
 
 impl Display for Platform {
-    #[allow(unused)]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for row in &self.data {
-            for ch in row {
-                write!(f, "{ch}")?;
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                write!(f, "{}", self.get(row, col))?;
             }
             writeln!(f)?;
         }
@@ -128,4 +430,88 @@ mod answers {
 
         platform.calc_weight()
     }
+
+    #[test_case(SAMPLE => 64; "with sample data")]
+    #[test_case(PERSONAL => 93102; "with personal data")]
+    pub fn problem2_in_place(input: &str) -> usize {
+        let mut platform = Platform::parse(input);
+        platform.spin_cycles(1_000_000_000);
+        platform.calc_weight()
+    }
+
+    #[test_case(SAMPLE; "with sample data")]
+    #[test_case(PERSONAL; "with personal data")]
+    pub fn tilts_match_rotations(input: &str) {
+        // Tilting any way should be the same as turning that edge to face
+        // north, tilting, and turning it back again
+        let platform = Platform::parse(input);
+        for (turns, direction) in [(0, Direction::North), (1, Direction::West), (2, Direction::South), (3, Direction::East)] {
+            let mut rotated = platform.clone();
+            for _ in 0..turns {
+                rotated = rotated.rotate_right();
+            }
+            rotated = rotated.tilt_north();
+            for _ in turns..4 {
+                rotated = rotated.rotate_right();
+            }
+
+            let mut tilted = platform.clone();
+            tilted.tilt(direction);
+            assert_eq!(tilted.to_string(), rotated.to_string(), "{direction:?}");
+        }
+    }
+
+    #[test]
+    pub fn rectangular_and_wide() {
+        // Wide enough that rocks have to roll between words
+        let row = format!("{}O{}#{}O", ".".repeat(60), ".".repeat(20), ".".repeat(50));
+        let mut platform = Platform::parse(&format!("{row}\n{}", ".".repeat(row.len())));
+        assert_eq!((platform.rows(), platform.cols()), (2, 133));
+
+        platform.tilt(Direction::East);
+        assert_eq!(platform.to_string().lines().next(), Some(format!("{}O#{}O", ".".repeat(80), ".".repeat(50)).as_str()));
+
+        platform.tilt(Direction::West);
+        assert_eq!(platform.to_string().lines().next(), Some(format!("O{}#O{}", ".".repeat(80), ".".repeat(50)).as_str()));
+
+        platform.tilt(Direction::South);
+        assert_eq!(platform.get(1, 0), 'O');
+        assert_eq!(platform.get(1, 82), 'O');
+        assert_eq!(platform.rotate_right().rotate_right().rotate_right().rotate_right().to_string(), platform.to_string());
+    }
+
+    #[test_case(SAMPLE, "N" => 136; "north only")]
+    #[test_case(SAMPLE, "N W S E x1000000000" => 64; "spin cycles")]
+    #[test_case(PERSONAL, "N W S E x1000000000" => 93102; "spin cycles with personal data")]
+    #[test_case(SAMPLE, "N x5 W" => 136; "north then west")]
+    #[test_case(SAMPLE, "" => 104; "doing nothing")]
+    pub fn scripts(input: &str, script: &str) -> usize {
+        let mut platform = Platform::parse(input);
+        platform.run(&Script::parse(script).expect("Should have been a valid script"));
+        platform.load(Direction::North)
+    }
+
+    #[test_case(SAMPLE; "with sample data")]
+    #[test_case(PERSONAL; "with personal data")]
+    pub fn load_on_every_edge(input: &str) {
+        // Loads on the other edges are the north load of the rotated platform
+        let mut rotated = Platform::parse(input);
+        for edge in [Direction::North, Direction::West, Direction::South, Direction::East] {
+            assert_eq!(Platform::parse(input).load(edge), rotated.load(Direction::North), "{edge:?}");
+            rotated = rotated.rotate_right();
+        }
+    }
+
+    #[test_case("N W x" => ScriptError::BadRepeat("x".into()); "without a count")]
+    #[test_case("N Q" => ScriptError::UnknownOperation("Q".into()); "with an unknown direction")]
+    #[test_case("N x2 x3" => ScriptError::NothingToRepeat; "with two counts in a row")]
+    pub fn script_errors(script: &str) -> ScriptError {
+        Script::parse(script).expect_err("Should have been invalid")
+    }
+
+    #[test_case("N W S E x1000000000"; "spin cycles")]
+    #[test_case("N E x3 S x0 W"; "several stages")]
+    pub fn script_round_trip(script: &str) {
+        assert_eq!(Script::parse(script).map(|s| s.to_string()), Ok(script.to_owned()));
+    }
 }
@@ -1,5 +1,7 @@
 //! Day 15 - Lens Library
 
+use std::{fmt::Display, hash::{BuildHasherDefault, Hasher}, io::{BufRead, BufReader, Read}};
+
 /// Calculate the Holiday ASCII String Helper value
 /// of a given string
 ///
@@ -10,12 +12,164 @@
 /// assert_eq!(52, holiday_hash("HASH"));
 /// ```
 pub fn holiday_hash(input: &str) -> usize {
-    input.as_bytes().iter().fold(0u8, |accum, &x| {
-        accum.wrapping_add(x).wrapping_mul(17)
-    }) as usize
+    let mut hasher = HolidayHasher::default();
+    hasher.write(input.as_bytes());
+    hasher.finish() as usize
+}
+
+/// The HASH algorithm as a [`Hasher`], so that it can back a standard
+/// `HashMap` or `HashSet`. Writing a string's bytes gives the same value as
+/// [`holiday_hash`], but hashing a `str` the usual way also feeds in a
+/// terminator byte, so a map's buckets won't line up with the puzzle's boxes.
+///
+/// ```
+/// # use aoc2023::day15::*;
+/// use std::{collections::HashMap, hash::{BuildHasher, Hasher}};
+///
+/// let mut hasher = HolidayHasher::default();
+/// hasher.write("HASH".as_bytes());
+/// assert_eq!(hasher.finish(), 52);
+/// assert_ne!(BuildHolidayHasher::default().hash_one("HASH"), 52);
+///
+/// let mut lenses = HashMap::with_hasher(BuildHolidayHasher::default());
+/// lenses.insert("rn", 1);
+/// assert_eq!(lenses.get("rn"), Some(&1));
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HolidayHasher {
+    state: u8,
+}
+
+impl Hasher for HolidayHasher {
+    fn finish(&self) -> u64 {
+        self.state as u64
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.state = bytes.iter().fold(self.state, |accum, &x| accum.wrapping_add(x).wrapping_mul(17));
+    }
 }
 
-/// Parse a single step
+/// Builds a [`HolidayHasher`] for each key
+pub type BuildHolidayHasher = BuildHasherDefault<HolidayHasher>;
+
+/// Which of the 256 boxes each of a set of labels lands in, to see how well
+/// (or badly) the HASH algorithm spreads them out
+///
+/// ```
+/// # use aoc2023::day15::*;
+/// let distribution = BucketDistribution::new(["rn", "cm", "qp", "rn"]);
+/// assert_eq!(distribution.bucket(0), ["cm", "rn"]);
+/// assert_eq!(distribution.colliding_pairs(), 1);
+/// assert_eq!(distribution.histogram(), vec![254, 1, 1]);
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BucketDistribution<'a> {
+    buckets: Vec<Vec<&'a str>>,
+}
+
+impl<'a> BucketDistribution<'a> {
+    /// Sort the labels into buckets. Repeated labels only count once, and
+    /// each bucket is kept in sorted order.
+    pub fn new(labels: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut buckets = vec![vec![]; LensLibrary::BOXES];
+        for label in labels {
+            let bucket: &mut Vec<&str> = &mut buckets[holiday_hash(label)];
+            if let Err(idx) = bucket.binary_search(&label) {
+                bucket.insert(idx, label);
+            }
+        }
+
+        Self { buckets }
+    }
+
+    pub fn bucket(&self, idx: usize) -> &[&'a str] {
+        &self.buckets[idx]
+    }
+
+    /// How many different labels there are
+    pub fn labels(&self) -> usize {
+        self.buckets.iter().map(Vec::len).sum()
+    }
+
+    /// How many buckets have at least one label in them
+    pub fn occupied(&self) -> usize {
+        self.buckets.iter().filter(|bucket| !bucket.is_empty()).count()
+    }
+
+    /// The most labels in any one bucket
+    pub fn max_load(&self) -> usize {
+        self.buckets.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    /// How many pairs of labels share a bucket
+    pub fn colliding_pairs(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.len() * bucket.len().saturating_sub(1) / 2).sum()
+    }
+
+    /// How many pairs of labels would be expected to share a bucket if the
+    /// hash spread them out perfectly at random, for comparison
+    pub fn expected_colliding_pairs(&self) -> f64 {
+        let labels = self.labels() as f64;
+        labels * (labels - 1.0) / 2.0 / self.buckets.len() as f64
+    }
+
+    /// How many buckets hold each number of labels, starting from zero
+    pub fn histogram(&self) -> Vec<usize> {
+        let mut histogram = vec![0; self.max_load() + 1];
+        for bucket in &self.buckets {
+            histogram[bucket.len()] += 1;
+        }
+        histogram
+    }
+
+    /// Every bucket with more than one label in it
+    pub fn collisions(&self) -> impl Iterator<Item = (usize, &[&'a str])> {
+        self.buckets.iter().enumerate()
+            .filter(|(_, bucket)| bucket.len() > 1)
+            .map(|(idx, bucket)| (idx, bucket.as_slice()))
+    }
+}
+
+/// A single step of the initialization sequence
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Operation {
+    /// Take the lens with this label out of its box
+    Remove(String),
+    /// Put a lens with this label and focal length in its box
+    Set(String, usize),
+}
+
+/// Ways that a step of the initialization sequence can be malformed. Each
+/// holds the step as it was written.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StepError {
+    /// There was no '=' or '-'
+    MissingOperator(String),
+    /// There was nothing before the operator
+    MissingLabel(String),
+    /// There was something after a '-'
+    TrailingCharacters(String),
+    /// The focal length after a '=' wasn't from 1 to 9
+    BadFocalLength(String),
+}
+
+impl Display for StepError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingOperator(step) => write!(f, "no '=' or '-' in step {step:?}"),
+            Self::MissingLabel(step) => write!(f, "no label in step {step:?}"),
+            Self::TrailingCharacters(step) => write!(f, "unexpected characters after '-' in step {step:?}"),
+            Self::BadFocalLength(step) => write!(f, "focal length should be from 1 to 9 in step {step:?}"),
+        }
+    }
+}
+
+impl std::error::Error for StepError {}
+
+/// Parse a single step into its label, operator and focal length. This is
+/// [`Operation::parse`] in the shape it used to have, and panics on steps
+/// that it rejects.
 ///
 /// Examples
 /// ```
@@ -25,9 +179,279 @@ pub fn holiday_hash(input: &str) -> usize {
 /// assert_eq!(parse_step("cn-"), ("cn", '-', None));
 /// ```
 pub fn parse_step(step: &str) -> (&str, char, Option<usize>) {
-    let op_idx = step.find(|ch| ch == '=' || ch == '-').unwrap();
-    let (label, rest) = step.split_at(op_idx);
-    (label, rest.chars().next().unwrap(), rest[1..].parse().ok())
+    let op = Operation::parse(step).expect("Should have been a valid step");
+    let label = &step[..op.label().len()];
+    match op {
+        Operation::Remove(_) => (label, '-', None),
+        Operation::Set(_, focal_length) => (label, '=', Some(focal_length)),
+    }
+}
+
+impl Operation {
+    /// Parse a single step
+    ///
+    /// Examples
+    /// ```
+    /// # use aoc2023::day15::*;
+    ///
+    /// assert_eq!(Operation::parse("ot=7"), Ok(Operation::Set("ot".into(), 7)));
+    /// assert_eq!(Operation::parse("cn-"), Ok(Operation::Remove("cn".into())));
+    /// assert_eq!(Operation::parse("cn=10"), Err(StepError::BadFocalLength("cn=10".into())));
+    /// ```
+    pub fn parse(step: &str) -> Result<Self, StepError> {
+        let op_idx = step.find(['=', '-']).ok_or_else(|| StepError::MissingOperator(step.to_owned()))?;
+        let (label, rest) = step.split_at(op_idx);
+        if label.is_empty() {
+            return Err(StepError::MissingLabel(step.to_owned()));
+        }
+
+        match rest.split_at(1) {
+            ("-", "") => Ok(Self::Remove(label.to_owned())),
+            ("-", _) => Err(StepError::TrailingCharacters(step.to_owned())),
+            (_, focal_length) => match focal_length.parse() {
+                Ok(focal_length @ 1..=9) => Ok(Self::Set(label.to_owned(), focal_length)),
+                _ => Err(StepError::BadFocalLength(step.to_owned())),
+            },
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Remove(label) | Self::Set(label, _) => label,
+        }
+    }
+}
+
+/// Written the same way as in the initialization sequence
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Remove(label) => write!(f, "{label}-"),
+            Self::Set(label, focal_length) => write!(f, "{label}={focal_length}"),
+        }
+    }
+}
+
+/// Ways that reading an initialization sequence can fail
+#[derive(Debug)]
+pub enum SequenceError {
+    Io(std::io::Error),
+    /// A step wasn't valid UTF-8, holding the step as it was read
+    NotUtf8(Vec<u8>),
+    Step(StepError),
+}
+
+impl Display for SequenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "couldn't read the sequence: {err}"),
+            Self::NotUtf8(step) => write!(f, "step {:?} isn't valid UTF-8", String::from_utf8_lossy(step)),
+            Self::Step(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for SequenceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::NotUtf8(_) => None,
+            Self::Step(err) => Some(err),
+        }
+    }
+}
+
+/// The steps of an initialization sequence, read one at a time. Newlines
+/// are ignored wherever they are, and empty steps are skipped, so a
+/// trailing newline or comma is fine.
+///
+/// ```
+/// # use aoc2023::day15::*;
+/// let steps = Sequence::from_reader("rn=1,c\nm-\n".as_bytes())
+///     .collect::<Result<Vec<_>, _>>()
+///     .expect("Should have been a valid sequence");
+/// assert_eq!(steps, vec![Operation::Set("rn".into(), 1), Operation::Remove("cm".into())]);
+/// ```
+pub struct Sequence<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    failed: bool,
+}
+
+impl<R: BufRead> Sequence<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, buffer: vec![], failed: false }
+    }
+}
+
+impl<R: Read> Sequence<BufReader<R>> {
+    pub fn from_reader(reader: R) -> Self {
+        Self::new(BufReader::new(reader))
+    }
+}
+
+impl<R: BufRead> Iterator for Sequence<R> {
+    type Item = Result<Operation, SequenceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // There's no telling where a broken reader would pick up again
+        if self.failed {
+            return None;
+        }
+
+        loop {
+            self.buffer.clear();
+            match self.reader.read_until(b',', &mut self.buffer) {
+                Ok(0) => return None,
+                Ok(_) => {},
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(SequenceError::Io(err)));
+                },
+            }
+
+            self.buffer.retain(|&byte| !matches!(byte, b',' | b'\n' | b'\r'));
+            if !self.buffer.is_empty() {
+                return Some(match std::str::from_utf8(&self.buffer) {
+                    Ok(step) => Operation::parse(step).map_err(SequenceError::Step),
+                    Err(_) => Err(SequenceError::NotUtf8(self.buffer.clone())),
+                });
+            }
+        }
+    }
+}
+
+/// The Holiday ASCII String Helper Manual Arrangement Procedure, i.e. a
+/// hash map with 256 boxes, chosen by [`holiday_hash`]. Each box keeps its
+/// entries in the order they were first inserted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HolidayMap<V> {
+    boxes: Vec<Vec<(String, V)>>,
+}
+
+/// The boxes of lenses, keyed by label with focal lengths as values
+pub type LensLibrary = HolidayMap<usize>;
+
+impl<V> HolidayMap<V> {
+    pub const BOXES: usize = 256;
+
+    pub fn new() -> Self {
+        Self { boxes: std::iter::repeat_with(Vec::new).take(Self::BOXES).collect() }
+    }
+
+    /// Put a value into the box for its label. If there's already one with
+    /// that label then it's replaced where it is, and the old value returned,
+    /// otherwise it goes at the back of the box.
+    pub fn insert(&mut self, label: &str, value: V) -> Option<V> {
+        let this_box = &mut self.boxes[holiday_hash(label)];
+        match this_box.iter_mut().find(|(existing, _)| existing == label) {
+            Some((_, spot)) => Some(std::mem::replace(spot, value)),
+            None => {
+                this_box.push((label.to_owned(), value));
+                None
+            },
+        }
+    }
+
+    /// Take the value with the given label out of its box, moving everything
+    /// behind it forward
+    pub fn remove(&mut self, label: &str) -> Option<V> {
+        let this_box = &mut self.boxes[holiday_hash(label)];
+        let idx = this_box.iter().position(|(existing, _)| existing == label)?;
+        Some(this_box.remove(idx).1)
+    }
+
+    pub fn get(&self, label: &str) -> Option<&V> {
+        self.boxes[holiday_hash(label)].iter()
+            .find(|(existing, _)| existing == label)
+            .map(|(_, value)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.boxes.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.boxes.iter().all(Vec::is_empty)
+    }
+
+    /// Everything in the given box, front to back
+    pub fn contents(&self, box_number: usize) -> impl Iterator<Item = (&str, &V)> {
+        self.boxes[box_number].iter().map(|(label, value)| (label.as_str(), value))
+    }
+
+    /// Every entry as (box, slot, label, value), box by box and front to back
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &str, &V)> {
+        self.boxes.iter().enumerate().flat_map(|(box_number, contents)| {
+            contents.iter().enumerate().map(move |(slot, (label, value))| (box_number, slot, label.as_str(), value))
+        })
+    }
+
+    /// Describe the map as it is after the given step, in the same format
+    /// as the puzzle's walkthrough
+    pub fn after<'a>(&'a self, step: &'a str) -> After<'a, V> {
+        After { step, map: self }
+    }
+}
+
+impl<V> Default for HolidayMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LensLibrary {
+    /// Carry out a single step of the initialization sequence
+    pub fn apply(&mut self, operation: &Operation) {
+        match operation {
+            Operation::Remove(label) => {
+                self.remove(label);
+            },
+            Operation::Set(label, focal_length) => {
+                self.insert(label, *focal_length);
+            },
+        }
+    }
+
+    /// The total focusing power of every lens in every box
+    pub fn focusing_power(&self) -> usize {
+        self.iter().map(|(box_number, slot, _, focal_length)| (box_number + 1) * (slot + 1) * focal_length).sum()
+    }
+}
+
+/// Lists the boxes with something in them, one per line
+impl<V: Display> Display for HolidayMap<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (box_number, contents) in self.boxes.iter().enumerate().filter(|(_, contents)| !contents.is_empty()) {
+            write!(f, "Box {box_number}:")?;
+            for (label, value) in contents {
+                write!(f, " [{label} {value}]")?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A [`HolidayMap`] along with the step that led to it
+///
+/// ```
+/// # use aoc2023::day15::*;
+/// let mut library = LensLibrary::new();
+/// library.apply(&Operation::Set("rn".into(), 1));
+/// assert_eq!(library.after("rn=1").to_string(), "After \"rn=1\":\nBox 0: [rn 1]\n\n");
+/// ```
+pub struct After<'a, V> {
+    step: &'a str,
+    map: &'a HolidayMap<V>,
+}
+
+impl<'a, V: Display> Display for After<'a, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "After {:?}:", self.step)?;
+        writeln!(f, "{}", self.map)
+    }
 }
 
 #[cfg(test)]
@@ -47,21 +471,21 @@ mod answers {
     #[test_case(SAMPLE => 145; "with sample data")]
     #[test_case(PERSONAL => 267372; "with personal data")]
     pub fn problem2(input: &str) -> usize {
-        let mut boxes: Vec<Vec<(&str, usize)>> = vec![vec![]; 256];
+        let mut boxes: Vec<Vec<(String, usize)>> = vec![vec![]; 256];
 
-        for (label, op, arg) in input.trim_end().split(',').map(parse_step) {
-            let this_box = &mut boxes[holiday_hash(label)];
-            match (op, arg) {
-                ('-', None) => {
+        for op in input.trim_end().split(',').map(Operation::parse) {
+            let op = op.expect("Should have been a valid step");
+            let this_box = &mut boxes[holiday_hash(op.label())];
+            match op {
+                Operation::Remove(label) => {
                     this_box.retain(|b| b.0 != label);
                 },
-                ('=', Some(flen)) => {
+                Operation::Set(label, flen) => {
                     match this_box.iter_mut().find(|e| e.0 == label) {
                         Some(spot) => spot.1 = flen,
                         None => this_box.push((label, flen)),
                     }
                 },
-                _ => unimplemented!("wtf")
             };
         }
 
@@ -70,4 +494,181 @@ mod answers {
         )
         .sum()
     }
+
+    #[test_case(SAMPLE => 145; "with sample data")]
+    #[test_case(PERSONAL => 267372; "with personal data")]
+    pub fn problem2_with_library(input: &str) -> usize {
+        let mut library = LensLibrary::new();
+        for op in Sequence::from_reader(input.as_bytes()) {
+            library.apply(&op.expect("Should have been a valid step"));
+        }
+        library.focusing_power()
+    }
+
+    #[test]
+    pub fn sample_trace() {
+        let mut library = LensLibrary::new();
+        let trace = SAMPLE.trim_end().split(',').map(|step| {
+            library.apply(&Operation::parse(step).expect("Should have been a valid step"));
+            library.after(step).to_string()
+        })
+        .collect::<String>();
+
+        assert_eq!(trace.trim_end(), SAMPLE_TRACE);
+    }
+
+    #[test]
+    pub fn map_operations() {
+        let mut map = HolidayMap::new();
+        assert_eq!(map.insert("rn", 'a'), None);
+        assert_eq!(map.insert("cm", 'b'), None);
+        assert_eq!(map.insert("rn", 'c'), Some('a'));
+        assert_eq!(map.get("rn"), Some(&'c'));
+        assert_eq!(map.contents(0).collect::<Vec<_>>(), vec![("rn", &'c'), ("cm", &'b')]);
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.remove("rn"), Some('c'));
+        assert_eq!(map.remove("rn"), None);
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(0, 0, "cm", &'b')]);
+        assert_eq!(map.remove("cm"), Some('b'));
+        assert!(map.is_empty());
+    }
+
+    #[test_case("rn" => StepError::MissingOperator("rn".into()); "without an operator")]
+    #[test_case("=1" => StepError::MissingLabel("=1".into()); "without a label")]
+    #[test_case("rn-1" => StepError::TrailingCharacters("rn-1".into()); "with a focal length to remove")]
+    #[test_case("rn=" => StepError::BadFocalLength("rn=".into()); "without a focal length")]
+    #[test_case("rn=0" => StepError::BadFocalLength("rn=0".into()); "with a zero focal length")]
+    #[test_case("rn=x" => StepError::BadFocalLength("rn=x".into()); "with a focal length that isn't a number")]
+    pub fn step_errors(step: &str) -> StepError {
+        Operation::parse(step).expect_err("Should have been invalid")
+    }
+
+    #[test]
+    pub fn streaming() {
+        // Newlines can turn up anywhere, even in the middle of a step
+        let input = "rn=1,\ncm-,q\r\np=3,,\n";
+        let steps = Sequence::from_reader(input.as_bytes()).map(|op| op.map(|op| op.to_string())).collect::<Result<Vec<_>, _>>();
+        assert_eq!(steps.expect("Should have been a valid sequence"), vec!["rn=1", "cm-", "qp=3"]);
+
+        // Bad steps are reported without stopping the rest
+        let steps = Sequence::from_reader("rn=1,cm,qp=3".as_bytes()).map(|op| op.ok()).collect::<Vec<_>>();
+        assert_eq!(steps, vec![Some(Operation::Set("rn".into(), 1)), None, Some(Operation::Set("qp".into(), 3))]);
+
+        // So are steps that aren't valid UTF-8, rather than being mangled
+        let steps = Sequence::from_reader(&b"rn=1,c\xffm-,qp=3"[..]).collect::<Vec<_>>();
+        assert!(matches!(&steps[1], Err(SequenceError::NotUtf8(step)) if step == b"c\xffm-"));
+        assert_eq!(steps.len(), 3);
+        assert!(steps[2].is_ok());
+
+        // Small reads shouldn't make a difference
+        let reader = std::io::BufReader::with_capacity(3, PERSONAL.as_bytes());
+        let steps = Sequence::new(reader).collect::<Result<Vec<_>, _>>().expect("Should have been a valid sequence");
+        assert_eq!(steps.iter().map(|op| op.to_string()).collect::<Vec<_>>().join(","), PERSONAL.trim_end());
+    }
+
+    #[test_case("HASH" => 52; "with the worked example")]
+    #[test_case("rn=1" => 30; "with the first sample step")]
+    #[test_case("cm-" => 253; "with a removal")]
+    #[test_case("ot=7" => 231; "with the last sample step")]
+    #[test_case("" => 0; "with nothing")]
+    pub fn hasher_values(step: &str) -> u64 {
+        let mut hasher = HolidayHasher::default();
+        hasher.write(step.as_bytes());
+        hasher.finish()
+    }
+
+    #[test_case(SAMPLE; "with sample data")]
+    #[test_case(PERSONAL; "with personal data")]
+    pub fn hasher_matches_hash(input: &str) {
+        for step in input.trim_end().split(',') {
+            // The HASH algorithm as the puzzle states it, one character at a time
+            let expected = step.chars().fold(0, |value, ch| (value + ch as usize) * 17 % 256);
+
+            let mut hasher = HolidayHasher::default();
+            hasher.write(step.as_bytes());
+            assert_eq!(hasher.finish() as usize, expected, "{step}");
+            assert_eq!(holiday_hash(step), expected, "{step}");
+        }
+    }
+
+    #[test]
+    pub fn backs_a_hash_map() {
+        use std::collections::HashMap;
+
+        let mut lenses = HashMap::with_hasher(BuildHolidayHasher::default());
+        for op in Sequence::from_reader(PERSONAL.as_bytes()) {
+            match op.expect("Should have been a valid step") {
+                Operation::Remove(label) => lenses.remove(&label),
+                Operation::Set(label, focal_length) => lenses.insert(label, focal_length),
+            };
+        }
+
+        let mut library = LensLibrary::new();
+        for op in Sequence::from_reader(PERSONAL.as_bytes()) {
+            library.apply(&op.expect("Should have been a valid step"));
+        }
+
+        assert_eq!(lenses.len(), library.len());
+        assert!(library.iter().all(|(_, _, label, focal_length)| lenses.get(label) == Some(focal_length)));
+    }
+
+    #[test]
+    pub fn personal_distribution() {
+        let labels = PERSONAL.trim_end().split(',')
+            .map(|step| Operation::parse(step).expect("Should have been a valid step"))
+            .collect::<Vec<_>>();
+        let distribution = BucketDistribution::new(labels.iter().map(Operation::label));
+
+        let histogram = distribution.histogram();
+        assert_eq!(histogram.iter().sum::<usize>(), 256);
+        assert_eq!(histogram.iter().enumerate().map(|(load, count)| load * count).sum::<usize>(), distribution.labels());
+        assert_eq!(256 - histogram[0], distribution.occupied());
+        assert_eq!(
+            distribution.collisions().map(|(_, bucket)| bucket.len() * (bucket.len() - 1) / 2).sum::<usize>(),
+            distribution.colliding_pairs(),
+        );
+        assert!(distribution.collisions().all(|(idx, bucket)| bucket.iter().all(|&label| holiday_hash(label) == idx)));
+    }
+
+    const SAMPLE_TRACE: &str = r#"After "rn=1":
+Box 0: [rn 1]
+
+After "cm-":
+Box 0: [rn 1]
+
+After "qp=3":
+Box 0: [rn 1]
+Box 1: [qp 3]
+
+After "cm=2":
+Box 0: [rn 1] [cm 2]
+Box 1: [qp 3]
+
+After "qp-":
+Box 0: [rn 1] [cm 2]
+
+After "pc=4":
+Box 0: [rn 1] [cm 2]
+Box 3: [pc 4]
+
+After "ot=9":
+Box 0: [rn 1] [cm 2]
+Box 3: [pc 4] [ot 9]
+
+After "ab=5":
+Box 0: [rn 1] [cm 2]
+Box 3: [pc 4] [ot 9] [ab 5]
+
+After "pc-":
+Box 0: [rn 1] [cm 2]
+Box 3: [ot 9] [ab 5]
+
+After "pc=6":
+Box 0: [rn 1] [cm 2]
+Box 3: [ot 9] [ab 5] [pc 6]
+
+After "ot=7":
+Box 0: [rn 1] [cm 2]
+Box 3: [ot 7] [ab 5] [pc 6]"#;
 }
@@ -2,7 +2,7 @@
 
 use std::{collections::{HashMap, HashSet, VecDeque}, fmt::Display};
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
 pub enum Direction {
     North,
     East,
@@ -10,7 +10,7 @@ pub enum Direction {
     West,
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct Position {
     pub row: usize,
     pub col: usize,
@@ -21,44 +21,119 @@ pub struct Position {
 impl Position {
     pub fn going(&self, dir: Direction) -> Option<(Self, Direction)> {
         match dir {
-            Direction::North => (self.row > 0).then_some(Self { row: self.row - 1, ..*self }),
-            Direction::South => (self.row < self.max_row).then_some(Self { row: self.row + 1, ..*self }),
-            Direction::West => (self.col > 0).then_some(Self { col: self.col - 1, ..*self }),
-            Direction::East => (self.col < self.max_col).then_some(Self { col: self.col + 1, ..*self }),
+            Direction::North => (self.row > 0).then(|| Self { row: self.row - 1, ..*self }),
+            Direction::South => (self.row < self.max_row).then(|| Self { row: self.row + 1, ..*self }),
+            Direction::West => (self.col > 0).then(|| Self { col: self.col - 1, ..*self }),
+            Direction::East => (self.col < self.max_col).then(|| Self { col: self.col + 1, ..*self }),
         }
         .map(|p| (p, dir))
     }
 }
 
+impl Direction {
+    pub const ALL: [Self; 4] = [Self::North, Self::East, Self::South, Self::West];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Which way the beam is going, the way the puzzle draws it
+    pub fn as_arrow(self) -> char {
+        match self {
+            Self::North => '^',
+            Self::East => '>',
+            Self::South => 'v',
+            Self::West => '<',
+        }
+    }
+}
+
+/// A set of directions packed into the bottom four bits of a byte
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct DirectionSet(u8);
+
+impl DirectionSet {
+    /// Add a direction, returning whether it's new
+    pub fn insert(&mut self, direction: Direction) -> bool {
+        let new = !self.contains(direction);
+        self.0 |= direction.bit();
+        new
+    }
+
+    pub fn contains(&self, direction: Direction) -> bool {
+        self.0 & direction.bit() != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Direction> {
+        Direction::ALL.into_iter().filter(move |&direction| self.contains(direction))
+    }
+}
+
 impl Display for Position {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "({},{})", self.row, self.col)
     }
 }
 
+/// Ways that the puzzle input can fail to describe a contraption
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// There were no rows at all
+    Empty,
+    /// A row was a different length to the first one
+    Ragged { row: usize },
+    /// Something other than empty space, a mirror or a splitter
+    UnknownTile(char),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty contraption"),
+            Self::Ragged { row } => write!(f, "row {row} has a different length to the first"),
+            Self::UnknownTile(ch) => write!(f, "unknown tile {ch:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The contraption, stored row by row
 #[derive(Default)]
 pub struct Grid {
-    nodes: HashMap<Position, char>,
+    tiles: Vec<char>,
     pub max_row: usize,
     pub max_col: usize,
 }
 
 impl Grid {
-    pub fn parse(input: &str) -> Self {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
         let lines = input.lines().collect::<Vec<_>>();
-        let max_row = lines.len() - 1;
-        let max_col = lines[0].len() - 1;
+        let cols = lines.first().map(|line| line.chars().count()).filter(|&cols| cols > 0).ok_or(ParseError::Empty)?;
 
-        let mut this = Self { max_row, max_col, ..Default::default() };
+        let mut tiles = Vec::with_capacity(lines.len() * cols);
+        for (row, line) in lines.iter().enumerate() {
+            if line.chars().count() != cols {
+                return Err(ParseError::Ragged { row });
+            }
 
-        for (row, line) in lines.into_iter().enumerate() {
-            for (col, ch) in line.chars().enumerate() {
-                let node = this.position(row, col);
-                this.nodes.insert(node, ch);
+            for tile in line.chars() {
+                match tile {
+                    '.' | '/' | '\\' | '-' | '|' => tiles.push(tile),
+                    other => return Err(ParseError::UnknownTile(other)),
+                }
             }
         }
 
-        this
+        Ok(Self { tiles, max_row: lines.len() - 1, max_col: cols - 1 })
     }
 
     pub fn position(&self, row: usize, col: usize) -> Position {
@@ -70,56 +145,512 @@ impl Grid {
         }
     }
 
+    /// What's at the given position
+    pub fn tile(&self, pos: &Position) -> Option<char> {
+        (pos.row <= self.max_row && pos.col <= self.max_col).then(|| self.tiles[self.index(pos.row, pos.col)])
+    }
+
+    fn index(&self, row: usize, col: usize) -> usize {
+        row * (self.max_col + 1) + col
+    }
+
+    /// Every place a beam can come in from the edge, and which way it's going
+    pub fn entry_points(&self) -> impl Iterator<Item = (Position, Direction)> + '_ {
+        let columns = (0..=self.max_col).flat_map(|col| [
+            (self.position(0, col), Direction::South),
+            (self.position(self.max_row, col), Direction::North),
+        ]);
+        let rows = (0..=self.max_row).flat_map(|row| [
+            (self.position(row, 0), Direction::East),
+            (self.position(row, self.max_col), Direction::West),
+        ]);
+
+        columns.chain(rows)
+    }
+
+    /// Every tile a beam passes through, along with which ways it went
     pub fn energize(&self, start_pos: Position, direction: Direction) -> HashMap<Position, HashSet<Direction>> {
-        let mut visited: HashMap<Position, HashSet<Direction>> = HashMap::new();
-        let mut queue = VecDeque::from([(start_pos, direction)]);
+        self.energize_dense(start_pos, direction).into()
+    }
 
-        while let Some((next, direction)) = queue.pop_front() {
-            let dirs = visited.entry(next.clone()).or_default();
-            if !dirs.contains(&direction) {
-                dirs.insert(direction.clone());
-                queue.extend(self.propagate_beam(next, direction));
+    /// The same as [`Grid::energize`], but with one entry per tile of the
+    /// grid rather than a hash map
+    ///
+    /// ```
+    /// # use aoc2023::day16::*;
+    /// let grid = Grid::parse(r".\.
+    /// ...").unwrap();
+    /// let energized = grid.energize_dense(grid.position(0, 0), Direction::East);
+    /// assert_eq!(energized.len(), 3);
+    /// assert_eq!(energized.tiles().map(|pos| pos.to_string()).collect::<Vec<_>>(), vec!["(0,0)", "(0,1)", "(1,1)"]);
+    /// ```
+    pub fn energize_dense(&self, start_pos: Position, direction: Direction) -> Energized {
+        let mut energized = Energized {
+            visited: vec![DirectionSet::default(); self.tiles.len()],
+            count: 0,
+            max_row: self.max_row,
+            max_col: self.max_col,
+        };
+        let mut queue = VecDeque::from([(start_pos.row, start_pos.col, direction)]);
+
+        while let Some((row, col, direction)) = queue.pop_front() {
+            let idx = self.index(row, col);
+            if energized.visited[idx].is_empty() {
+                energized.count += 1;
+            }
+
+            if energized.visited[idx].insert(direction) {
+                queue.extend(self.next_states(row, col, direction));
             }
         }
 
-        visited
+        energized
+    }
+
+    /// Where a beam goes next from the given tile, as (row, col, direction)
+    fn next_states(&self, row: usize, col: usize, direction: Direction) -> impl Iterator<Item = (usize, usize, Direction)> + '_ {
+        bounce(self.tiles[self.index(row, col)], direction).into_iter().flatten().filter_map(move |direction| {
+            let (row, col) = match direction {
+                Direction::North => (row.checked_sub(1)?, col),
+                Direction::South => (row + 1, col),
+                Direction::West => (row, col.checked_sub(1)?),
+                Direction::East => (row, col + 1),
+            };
+            (row <= self.max_row && col <= self.max_col).then_some((row, col, direction))
+        })
+    }
+
+    /// Send a beam in and report on where it went: what it energized, any
+    /// loops it got caught in, and which mirrors and splitters it missed
+    pub fn report(&self, start_pos: Position, direction: Direction) -> BeamReport {
+        let energized = self.energize_dense(start_pos, direction);
+
+        // Number every state the beam was in, then look for groups of them
+        // that lead back round to each other
+        let states = energized.states().collect::<Vec<_>>();
+        let ids = states.iter().enumerate().map(|(id, (pos, direction))| ((pos.row, pos.col, *direction), id)).collect::<HashMap<_, _>>();
+        let edges = states.iter()
+            .map(|(pos, direction)| self.next_states(pos.row, pos.col, *direction).map(|state| ids[&state]).collect())
+            .collect::<Vec<Vec<_>>>();
+
+        let (components, count) = strongly_connected(&edges);
+        let mut loops = vec![vec![]; count];
+        for (id, state) in states.iter().enumerate() {
+            loops[components[id]].push(state.clone());
+        }
+
+        // A state can't lead straight back to itself, so anything on its
+        // own isn't part of a loop
+        loops.retain(|states| states.len() > 1);
+        loops.iter_mut().for_each(|states| states.sort());
+        loops.sort();
+
+        let never_hit = (0..=self.max_row)
+            .flat_map(|row| (0..=self.max_col).map(move |col| (row, col)))
+            .map(|(row, col)| self.position(row, col))
+            .filter_map(|pos| self.tile(&pos).filter(|&tile| tile != '.').map(|tile| (pos, tile)))
+            .filter(|(pos, _)| !energized.contains(pos))
+            .collect();
+
+        BeamReport { energized, loops, never_hit }
     }
 
     pub fn propagate_beam(&self, next: Position, direction: Direction) -> impl IntoIterator<Item = (Position, Direction)> {
-        match self.nodes.get(&next) {
-            Some('.') => vec![ next.going(direction.clone()) ],
-            Some('/') => {
-                match direction {
-                    Direction::North => vec![ next.going(Direction::East) ],
-                    Direction::South => vec![ next.going(Direction::West) ],
-                    Direction::West => vec![ next.going(Direction::South) ],
-                    Direction::East => vec![ next.going(Direction::North) ],
-                }
-            },
-            Some('\\') => {
-                match direction {
-                    Direction::North => vec![ next.going(Direction::West) ],
-                    Direction::South => vec![ next.going(Direction::East) ],
-                    Direction::West => vec![ next.going(Direction::North) ],
-                    Direction::East => vec![ next.going(Direction::South) ],
+        let tile = self.tile(&next).expect("Should have been on the grid");
+        bounce(tile, direction).into_iter().flatten().filter_map(move |direction| next.going(direction))
+    }
+}
+
+/// Which way a beam leaves a tile, given which way it was going
+fn bounce(tile: char, direction: Direction) -> [Option<Direction>; 2] {
+    match tile {
+        '.' => [ Some(direction), None ],
+        '/' => {
+            match direction {
+                Direction::North => [ Some(Direction::East), None ],
+                Direction::South => [ Some(Direction::West), None ],
+                Direction::West => [ Some(Direction::South), None ],
+                Direction::East => [ Some(Direction::North), None ],
+            }
+        },
+        '\\' => {
+            match direction {
+                Direction::North => [ Some(Direction::West), None ],
+                Direction::South => [ Some(Direction::East), None ],
+                Direction::West => [ Some(Direction::North), None ],
+                Direction::East => [ Some(Direction::South), None ],
+            }
+        },
+        '-' => {
+            match direction {
+                Direction::West | Direction::East => [ Some(direction), None ],
+                Direction::North | Direction::South => [ Some(Direction::East), Some(Direction::West) ],
+            }
+        }
+        '|' => {
+            match direction {
+                Direction::North | Direction::South => [ Some(direction), None ],
+                Direction::East | Direction::West => [ Some(Direction::North), Some(Direction::South) ],
+            }
+        }
+        _ => unreachable!("Grid::parse should only have accepted known tiles")
+    }
+}
+
+/// The tiles energized by a beam, and which ways the beam crossed each one
+pub struct Energized {
+    visited: Vec<DirectionSet>,
+    count: usize,
+    max_row: usize,
+    max_col: usize,
+}
+
+impl Energized {
+    /// How many tiles are energized
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn contains(&self, pos: &Position) -> bool {
+        !self.directions(pos).is_empty()
+    }
+
+    /// Which ways the beam went through the given tile
+    pub fn directions(&self, pos: &Position) -> DirectionSet {
+        if pos.row <= self.max_row && pos.col <= self.max_col {
+            self.visited[pos.row * (self.max_col + 1) + pos.col]
+        } else {
+            DirectionSet::default()
+        }
+    }
+
+    /// Every way the beam went through every tile, row by row
+    pub fn states(&self) -> impl Iterator<Item = (Position, Direction)> + '_ {
+        self.tiles().flat_map(move |pos| self.directions(&pos).iter().map(move |direction| (pos.clone(), direction)))
+    }
+
+    /// Draw the energized tiles as '#' and everything else as '.'
+    pub fn render(&self) -> String {
+        let mut rendered = String::new();
+
+        for row in self.visited.chunks(self.max_col + 1) {
+            rendered.extend(row.iter().map(|directions| if directions.is_empty() { '.' } else { '#' }));
+            rendered.push('\n');
+        }
+
+        rendered
+    }
+
+    /// Draw the beam on top of the contraption, the way the puzzle does.
+    /// Empty space the beam crossed one way shows which way it went, and
+    /// space it crossed several ways shows how many.
+    pub fn render_beams(&self, grid: &Grid) -> String {
+        let mut rendered = String::new();
+
+        for (row, directions) in self.visited.chunks(self.max_col + 1).enumerate() {
+            for (col, directions) in directions.iter().enumerate() {
+                let tile = grid.tile(&grid.position(row, col)).expect("Should have been on the grid");
+                rendered.push(match (tile, directions.len()) {
+                    ('.', 0) => '.',
+                    ('.', 1) => directions.iter().next().map_or('.', Direction::as_arrow),
+                    ('.', n) => char::from_digit(n as u32, 10).unwrap_or('*'),
+                    (tile, _) => tile,
+                });
+            }
+            rendered.push('\n');
+        }
+
+        rendered
+    }
+
+    /// Every energized tile, row by row
+    pub fn tiles(&self) -> impl Iterator<Item = Position> + '_ {
+        self.visited.iter().enumerate()
+            .filter(|(_, directions)| !directions.is_empty())
+            .map(|(idx, _)| Position {
+                row: idx / (self.max_col + 1),
+                col: idx % (self.max_col + 1),
+                max_row: self.max_row,
+                max_col: self.max_col,
+            })
+    }
+}
+
+impl From<Energized> for HashMap<Position, HashSet<Direction>> {
+    fn from(energized: Energized) -> Self {
+        energized.tiles()
+            .map(|pos| {
+                let directions = energized.directions(&pos).iter().collect();
+                (pos, directions)
+            })
+            .collect()
+    }
+}
+
+/// Everything about where a beam went
+pub struct BeamReport {
+    pub energized: Energized,
+    /// Every group of states the beam kept going round, each in order of
+    /// position
+    pub loops: Vec<Vec<(Position, Direction)>>,
+    /// The mirrors and splitters the beam never reached
+    pub never_hit: Vec<(Position, char)>,
+}
+
+impl Display for BeamReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} tiles energized", self.energized.len())?;
+
+        for states in &self.loops {
+            let (start, direction) = &states[0];
+            writeln!(f, "Loop of {} states through {start} going {direction:?}", states.len())?;
+        }
+
+        for (pos, tile) in &self.never_hit {
+            writeln!(f, "Never hit {tile} at {pos}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A set of tiles, as a bitset indexed by `row * cols + col`
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Tiles(Vec<u64>);
+
+impl Tiles {
+    fn with_len(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+
+    fn insert(&mut self, idx: usize) {
+        self.0[idx / 64] |= 1 << (idx % 64);
+    }
+
+    fn union_with(&mut self, other: &Self) {
+        self.0.iter_mut().zip(&other.0).for_each(|(a, b)| *a |= b);
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+}
+
+/// The contraption boiled down to which splitters send beams to which other
+/// splitters. Between splitters a beam only ever goes one way, so all that
+/// matters about each splitter is which tiles its two beams cross before
+/// they stop, and where they stop.
+///
+/// Every splitter in a strongly connected component energizes exactly the
+/// same tiles, so those are worked out once per component, from the
+/// components downstream of it, and shared by every entry point that leads
+/// there.
+///
+/// ```
+/// # use aoc2023::day16::*;
+/// let grid = Grid::parse(".|.\n...\n.-.").unwrap();
+/// let graph = BeamGraph::new(&grid);
+/// assert_eq!(graph.energized(grid.position(0, 0), Direction::East), 6);
+/// assert_eq!(graph.best_entry(&grid).map(|(_, _, count)| count), Some(6));
+/// ```
+pub struct BeamGraph {
+    cols: usize,
+    splitters: HashMap<Position, usize>,
+    /// Which component each splitter belongs to
+    components: Vec<usize>,
+    /// Every tile energized by a beam hitting any splitter in each component
+    reach: Vec<Tiles>,
+    /// The tiles crossed by the beam from each entry point, and the splitter it stopped at
+    entries: HashMap<(Position, Direction), (Tiles, Option<usize>)>,
+}
+
+impl BeamGraph {
+    pub fn new(grid: &Grid) -> Self {
+        let cols = grid.max_col + 1;
+        let len = (grid.max_row + 1) * cols;
+
+        let splitters = (0..=grid.max_row)
+            .flat_map(|row| (0..=grid.max_col).map(move |col| (row, col)))
+            .map(|(row, col)| grid.position(row, col))
+            .filter(|pos| matches!(grid.tile(pos), Some('-' | '|')))
+            .enumerate()
+            .map(|(idx, pos)| (pos, idx))
+            .collect::<HashMap<_, _>>();
+
+        let mut graph = Self { cols, splitters, components: vec![], reach: vec![], entries: HashMap::new() };
+
+        // Follow both beams out of every splitter
+        let mut segments = vec![Tiles::with_len(len); graph.splitters.len()];
+        let mut edges = vec![vec![]; graph.splitters.len()];
+        for (pos, &idx) in &graph.splitters {
+            let outputs = match grid.tile(pos) {
+                Some('-') => [Direction::East, Direction::West],
+                _ => [Direction::North, Direction::South],
+            };
+
+            for direction in outputs {
+                let stop = graph.trace(grid, &mut segments[idx], (pos.clone(), direction));
+                edges[idx].extend(stop);
+            }
+        }
+
+        // Components come out downstream first, so by the time each one is
+        // reached everything it leads to has already been filled in
+        let (components, count) = strongly_connected(&edges);
+        let mut reach = vec![Tiles::with_len(len); count];
+        let mut members = vec![vec![]; count];
+        for (idx, &component) in components.iter().enumerate() {
+            members[component].push(idx);
+        }
+
+        for (component, members) in members.iter().enumerate() {
+            let mut tiles = Tiles::with_len(len);
+            for &idx in members {
+                tiles.union_with(&segments[idx]);
+                for &next in &edges[idx] {
+                    if components[next] != component {
+                        tiles.union_with(&reach[components[next]]);
+                    }
                 }
+            }
+            reach[component] = tiles;
+        }
+
+        for (pos, direction) in grid.entry_points() {
+            let mut tiles = Tiles::with_len(len);
+            let stop = graph.trace(grid, &mut tiles, (pos.clone(), direction));
+            graph.entries.insert((pos, direction), (tiles, stop));
+        }
+
+        graph.components = components;
+        graph.reach = reach;
+        graph
+    }
+
+    /// How many strongly connected components the splitters form
+    pub fn components(&self) -> usize {
+        self.reach.len()
+    }
+
+    /// How many tiles end up energized by a beam coming in from the edge
+    /// at the given position. Panics if that isn't a way in from the edge.
+    pub fn energized(&self, start: Position, direction: Direction) -> usize {
+        let (tiles, stop) = &self.entries[&(start, direction)];
+        match stop {
+            Some(splitter) => {
+                let mut tiles = tiles.clone();
+                tiles.union_with(&self.reach[self.components[*splitter]]);
+                tiles.len()
             },
-            Some('-') => {
-                match direction {
-                    Direction::West | Direction::East => vec![ next.going(direction.clone()) ],
-                    Direction::North | Direction::South => vec![ next.going(Direction::East), next.going(Direction::West) ],
+            None => tiles.len(),
+        }
+    }
+
+    /// The way in from the edge that energizes the most tiles, and how many
+    pub fn best_entry(&self, grid: &Grid) -> Option<(Position, Direction, usize)> {
+        grid.entry_points()
+            .map(|(pos, direction)| {
+                let count = self.energized(pos.clone(), direction);
+                (pos, direction, count)
+            })
+            .max_by_key(|(_, _, count)| *count)
+    }
+
+    /// Follow a beam until it leaves the grid, hits a splitter side on, or
+    /// comes back round to where it started, marking every tile it crosses.
+    /// Returns the splitter it stopped at, if any.
+    fn trace(&self, grid: &Grid, tiles: &mut Tiles, start: (Position, Direction)) -> Option<usize> {
+        let mut state = start.clone();
+        loop {
+            let (pos, direction) = &state;
+            tiles.insert(pos.row * self.cols + pos.col);
+
+            let side_on = match grid.tile(pos) {
+                Some('-') => matches!(direction, Direction::North | Direction::South),
+                Some('|') => matches!(direction, Direction::East | Direction::West),
+                _ => false,
+            };
+            if side_on {
+                return Some(self.splitters[pos]);
+            }
+
+            // Away from splitters a beam can be followed backwards just as
+            // well as forwards, so the only loop it can be in is one that
+            // goes through where it started
+            let (pos, direction) = state;
+            state = grid.propagate_beam(pos, direction).into_iter().next()?;
+            if state == start {
+                return None;
+            }
+        }
+    }
+}
+
+/// Tarjan's algorithm, returning the component of each node along with how
+/// many components there are. Components are numbered in the order they're
+/// finished, so every edge leads to a component with the same or a lower
+/// number.
+fn strongly_connected(edges: &[Vec<usize>]) -> (Vec<usize>, usize) {
+    const UNSEEN: usize = usize::MAX;
+
+    let mut index = vec![UNSEEN; edges.len()];
+    let mut low = vec![0; edges.len()];
+    let mut on_stack = vec![false; edges.len()];
+    let mut stack = vec![];
+    let mut components = vec![UNSEEN; edges.len()];
+    let (mut next_index, mut count) = (0, 0);
+
+    for root in 0..edges.len() {
+        if index[root] != UNSEEN {
+            continue;
+        }
+
+        index[root] = next_index;
+        low[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        // Each entry is a node along with which of its edges to look at next
+        let mut work = vec![(root, 0)];
+        while let Some((node, edge)) = work.pop() {
+            if let Some(&next) = edges[node].get(edge) {
+                work.push((node, edge + 1));
+                if index[next] == UNSEEN {
+                    index[next] = next_index;
+                    low[next] = next_index;
+                    next_index += 1;
+                    stack.push(next);
+                    on_stack[next] = true;
+                    work.push((next, 0));
+                } else if on_stack[next] {
+                    low[node] = low[node].min(index[next]);
                 }
+                continue;
             }
-            Some('|') => {
-                match direction {
-                    Direction::North | Direction::South => vec![ next.going(direction.clone()) ],
-                    Direction::East | Direction::West => vec![ next.going(Direction::North), next.going(Direction::South) ],
+
+            if low[node] == index[node] {
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    components[member] = count;
+                    if member == node {
+                        break;
+                    }
                 }
+                count += 1;
+            }
+
+            if let Some(&(parent, _)) = work.last() {
+                low[parent] = low[parent].min(low[node]);
             }
-            _ => unimplemented!("wtf")
         }
-        .into_iter().flatten()
     }
+
+    (components, count)
 }
 
 #[cfg(test)]
@@ -134,7 +665,7 @@ mod answers {
     #[test_case(SAMPLE => 46; "with sample data")]
     #[test_case(PERSONAL => 6816; "with personal data")]
     pub fn problem1(input: &str) -> usize {
-        let grid = Grid::parse(input);
+        let grid = Grid::parse(input).expect("Should have been a valid contraption");
         let start = grid.position(0, 0);
         grid.energize(start, Direction::East).len()
     }
@@ -142,7 +673,7 @@ mod answers {
     #[test_case(SAMPLE => 51; "with sample data")]
     #[test_case(PERSONAL => 8163; "with personal data")]
     pub fn problem2(input: &str) -> usize {
-        let grid = Grid::parse(input);
+        let grid = Grid::parse(input).expect("Should have been a valid contraption");
         let origin = grid.position(0, 0);
 
         let mut bh = BinaryHeap::<usize>::new();
@@ -165,4 +696,164 @@ mod answers {
 
         bh.pop().unwrap()
     }
+
+    #[test_case(SAMPLE => 51; "with sample data")]
+    #[test_case(PERSONAL => 8163; "with personal data")]
+    pub fn problem2_with_graph(input: &str) -> usize {
+        let grid = Grid::parse(input).expect("Should have been a valid contraption");
+        let graph = BeamGraph::new(&grid);
+        let (_, _, count) = graph.best_entry(&grid).expect("Should have had a way in");
+        count
+    }
+
+    #[test_case(SAMPLE; "with sample data")]
+    #[test_case(PERSONAL; "with personal data")]
+    pub fn graph_matches_simulation(input: &str) {
+        let grid = Grid::parse(input).expect("Should have been a valid contraption");
+        let graph = BeamGraph::new(&grid);
+
+        for (pos, direction) in grid.entry_points() {
+            let expected = grid.energize(pos.clone(), direction).len();
+            assert_eq!(graph.energized(pos.clone(), direction), expected, "{pos} {direction:?}");
+        }
+    }
+
+    #[test]
+    pub fn loops_back_through_a_splitter() {
+        // Both beams out of the splitter go round the same loop, and come
+        // back to pass straight through it again
+        let grid = Grid::parse(&[r"....", r"/-.\", r"....", r"\../"].join("\n")).expect("Should have been a valid contraption");
+        let graph = BeamGraph::new(&grid);
+
+        for (pos, direction) in grid.entry_points() {
+            let expected = grid.energize(pos.clone(), direction).len();
+            assert_eq!(graph.energized(pos.clone(), direction), expected, "{pos} {direction:?}");
+        }
+    }
+
+    #[test]
+    pub fn components() {
+        // Two splitters pointing at each other are one component
+        let grid = Grid::parse("|.-\n...\n-.|").expect("Should have been a valid contraption");
+        assert_eq!(BeamGraph::new(&grid).components(), 1);
+        assert_eq!(strongly_connected(&[vec![1], vec![2], vec![0, 3], vec![]]), (vec![1, 1, 1, 0], 2));
+    }
+
+    #[test_case(SAMPLE; "with sample data")]
+    #[test_case(PERSONAL; "with personal data")]
+    pub fn energized_tiles(input: &str) {
+        let grid = Grid::parse(input).expect("Should have been a valid contraption");
+        let energized = grid.energize_dense(grid.position(0, 0), Direction::East);
+        let tiles = energized.tiles().collect::<Vec<_>>();
+
+        assert_eq!(tiles.len(), energized.len());
+        assert!(tiles.iter().all(|pos| energized.contains(pos)));
+        assert!(tiles.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Every tile was entered some way, and every way is one of the four
+        let ways = tiles.iter().map(|pos| energized.directions(pos).len()).sum::<usize>();
+        assert!((tiles.len()..=tiles.len() * 4).contains(&ways));
+
+        // The hash map form holds exactly the same states
+        let map = grid.energize(grid.position(0, 0), Direction::East);
+        assert_eq!(map.len(), tiles.len());
+        assert!(tiles.iter().all(|pos| map[pos] == energized.directions(pos).iter().collect()));
+    }
+
+    #[test]
+    pub fn direction_sets() {
+        let mut set = DirectionSet::default();
+        assert!(set.is_empty());
+        assert!(set.insert(Direction::West));
+        assert!(set.insert(Direction::North));
+        assert!(!set.insert(Direction::West));
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![Direction::North, Direction::West]);
+    }
+
+    #[test]
+    pub fn render_sample() {
+        let grid = Grid::parse(SAMPLE).expect("Should have been a valid contraption");
+        let energized = grid.energize_dense(grid.position(0, 0), Direction::East);
+
+        let beams = r">|<<<\....
+|v-.\^....
+.v...|->>>
+.v...v^.|.
+.v...v^...
+.v...v^..\
+.v../2\\..
+<->-/vv|..
+.|<<<2-|.\
+.v//.|.v..
+";
+        assert_eq!(energized.render_beams(&grid), beams);
+
+        let tiles = "\
+######....
+.#...#....
+.#...#####
+.#...##...
+.#...##...
+.#...##...
+.#..####..
+########..
+.#######..
+.#...#.#..
+";
+        assert_eq!(energized.render(), tiles);
+    }
+
+    #[test]
+    pub fn report_loops() {
+        // Both beams out of the splitter go round the same ring of mirrors,
+        // one each way
+        let grid = Grid::parse(&[r"....", r"/-.\", r"....", r"\../"].join("\n")).expect("Should have been a valid contraption");
+        let report = grid.report(grid.position(0, 1), Direction::South);
+
+        assert_eq!(report.loops.len(), 2);
+        assert!(report.loops.iter().all(|states| states.len() == 10));
+        assert!(report.never_hit.is_empty());
+
+        let summary = report.to_string();
+        assert!(summary.starts_with("11 tiles energized\n"), "{summary}");
+        assert!(summary.contains("Loop of 10 states through (1,0) going North\n"), "{summary}");
+    }
+
+    #[test_case(SAMPLE; "with sample data")]
+    #[test_case(PERSONAL; "with personal data")]
+    pub fn report_is_consistent(input: &str) {
+        let grid = Grid::parse(input).expect("Should have been a valid contraption");
+        let report = grid.report(grid.position(0, 0), Direction::East);
+
+        // Every state in a loop leads to another state in the same loop
+        for states in &report.loops {
+            for (pos, direction) in states {
+                let next = grid.propagate_beam(pos.clone(), *direction).into_iter().collect::<Vec<_>>();
+                assert!(next.iter().any(|state| states.contains(state)), "{pos} {direction:?}");
+            }
+        }
+
+        for (pos, tile) in &report.never_hit {
+            assert!(!report.energized.contains(pos));
+            assert!("/\\-|".contains(*tile));
+        }
+    }
+
+    #[test]
+    pub fn report_never_hit() {
+        let grid = Grid::parse(&[r"..\", r"..-", r"|.."].join("\n")).expect("Should have been a valid contraption");
+        let report = grid.report(grid.position(0, 0), Direction::East);
+
+        assert_eq!(report.never_hit, vec![(grid.position(2, 0), '|')]);
+        assert!(report.loops.is_empty());
+        assert_eq!(report.to_string(), "6 tiles energized\nNever hit | at (2,0)\n");
+    }
+
+    #[test_case("" => ParseError::Empty; "with nothing")]
+    #[test_case("..\n..." => ParseError::Ragged { row: 1 }; "with a ragged row")]
+    #[test_case("..\n.#" => ParseError::UnknownTile('#'); "with an unknown tile")]
+    pub fn bad_contraptions(input: &str) -> ParseError {
+        Grid::parse(input).err().expect("Should have been rejected")
+    }
 }
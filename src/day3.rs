@@ -5,13 +5,114 @@ use std::collections::HashMap;
 
 pub type Schematic = HashMap<(usize, usize), (u8, Vec<u32>)>;
 
+/// Which characters in the schematic should be treated as symbols
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum SymbolClass {
+    /// Any ASCII punctuation other than '.', which is what the puzzle uses
+    #[default]
+    Punctuation,
+
+    /// Only the listed characters
+    OneOf(Vec<u8>),
+
+    /// Anything that isn't a digit, a '.', whitespace or one of the listed
+    /// characters
+    NoneOf(Vec<u8>),
+}
+
+impl SymbolClass {
+    /// Is the given character a symbol in this class?
+    ///
+    /// ```
+    /// # use aoc2023::day3::*;
+    /// assert!(SymbolClass::Punctuation.contains(b'*'));
+    /// assert!(!SymbolClass::Punctuation.contains(b'.'));
+    /// assert!(!SymbolClass::OneOf(vec![b'*']).contains(b'#'));
+    /// assert!(SymbolClass::NoneOf(vec![b'#']).contains(b'x'));
+    /// ```
+    pub fn contains(&self, ch: u8) -> bool {
+        match self {
+            Self::Punctuation => ch.is_ascii_punctuation() && ch != b'.',
+            Self::OneOf(symbols) => symbols.contains(&ch),
+            Self::NoneOf(excluded) => {
+                !ch.is_ascii_digit() && !ch.is_ascii_whitespace() && ch != b'.' && !excluded.contains(&ch)
+            }
+        }
+    }
+}
+
+/// A number in the schematic along with where it was found
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PartNumber {
+    pub value: u32,
+    pub row: usize,
+    pub col: usize,
+    pub len: usize,
+}
+
+impl PartNumber {
+    /// All of the locations that are adjacent to this number, including
+    /// diagonals. Locations past the right or bottom edge may be included,
+    /// but nothing ever underflows past the top or left.
+    pub fn neighbours(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.row.saturating_sub(1)..=self.row + 1).flat_map(move |j| {
+            (self.col.saturating_sub(1)..=self.col + self.len).map(move |k| (j, k))
+        })
+    }
+}
+
+/// Find every number in the schematic, whether or not it touches a symbol
+pub fn find_numbers(input: &str) -> Vec<PartNumber> {
+    let re = Regex::new(r"\d+").expect("Should have been a valid regex");
+
+    input.lines().enumerate()
+        .flat_map(|(row, line)| {
+            re.find_iter(line.trim_end())
+                .map(move |needle| PartNumber {
+                    value: needle.as_str().parse().expect("Should have been a valid number"),
+                    row,
+                    col: needle.start(),
+                    len: needle.len(),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Find every number that touches at least one symbol. Each number is
+/// returned exactly once, no matter how many symbols it's adjacent to.
+///
+/// ```
+/// # use aoc2023::day3::*;
+/// let input = "*..\n.12\n..#";
+/// let parts = part_numbers(input, &SymbolClass::Punctuation);
+/// assert_eq!(parts.iter().map(|p| p.value).sum::<u32>(), 12);
+/// ```
+pub fn part_numbers(input: &str, symbols: &SymbolClass) -> Vec<PartNumber> {
+    let lines = input.lines().map(str::as_bytes).collect::<Vec<_>>();
+    let is_symbol = |(row, col): (usize, usize)| {
+        lines.get(row).and_then(|line| line.get(col)).is_some_and(|&ch| symbols.contains(ch))
+    };
+
+    find_numbers(input).into_iter()
+        .filter(|number| number.neighbours().any(is_symbol))
+        .collect()
+}
+
 pub fn parse_schematic(input: &str) -> Schematic {
+    parse_schematic_with(input, &SymbolClass::default())
+}
+
+/// Build a map of every symbol to the numbers that are adjacent to it. A
+/// number that touches more than one symbol shows up under each of them, so
+/// use [`part_numbers`] instead if each number should only count once.
+pub fn parse_schematic_with(input: &str, symbols: &SymbolClass) -> Schematic {
     // First collect the location of all of the symbols in the input
-    let schematic = input.lines().enumerate().fold(Default::default(), |mut schematic, (row, line)| {
+    let mut schematic = input.lines().enumerate().fold(Schematic::default(), |mut schematic, (row, line)| {
         // Collect the location of all of the symbols on this line
         line.as_bytes().iter().enumerate()
             .filter_map(|(col, &ch)| {
-                if ch.is_ascii_punctuation() && ch != b'.' {
+                if symbols.contains(ch) {
                     Some(((row, col), (ch, vec![])))
                 } else {
                     None
@@ -23,22 +124,13 @@ pub fn parse_schematic(input: &str) -> Schematic {
     });
 
     // Now scan for all of the numbers and see what symbols they're near
-    input.lines().enumerate().fold(schematic, |mut schematic, (row, line)| {
-        let re = Regex::new(r"\d+").expect("Should have been a valid regex");
-        for needle in re.find_iter(line) {
-            let num: u32 = needle.as_str().parse().expect("Should have been a valid number");
-
-            // Figure out the edges of the number - these are the locations where a symbol
-            // is interesting
-            for j in row.saturating_sub(1)..=row+1 {
-                for k in needle.start().saturating_sub(1)..=needle.end() {
-                    schematic.entry((j, k)).and_modify(|c| c.1.push(num));
-                }
-            }
+    for number in find_numbers(input) {
+        for location in number.neighbours() {
+            schematic.entry(location).and_modify(|c| c.1.push(number.value));
         }
+    }
 
-        schematic
-    })
+    schematic
 }
 
 #[cfg(test)]
@@ -49,8 +141,9 @@ mod answers {
     #[test_case(SAMPLE_INPUT => 4361; "with sample data")]
     #[test_case(PERSONAL_INPUT => 536202; "with real data")]
     pub fn problem1(input: &str) -> u32 {
-        parse_schematic(input).values()
-            .flat_map(|symbol| symbol.1.iter()).sum()
+        part_numbers(input, &SymbolClass::Punctuation).iter()
+            .map(|part| part.value)
+            .sum()
     }
 
     #[test_case(SAMPLE_INPUT => 467835; "with sample data")]
@@ -62,6 +155,39 @@ mod answers {
             .sum()
     }
 
+    #[test_case(SHARED_BY_TWO_SYMBOLS => 100; "number between two symbols")]
+    #[test_case(SHARED_DIAGONALLY => 57; "number touching symbols on both diagonals")]
+    #[test_case(SAME_VALUE_TWICE => 14; "equal numbers at different spots")]
+    #[test_case(EDGES => 3; "numbers on the edges of the schematic")]
+    pub fn shared_adjacency(input: &str) -> u32 {
+        part_numbers(input, &SymbolClass::Punctuation).iter()
+            .map(|part| part.value)
+            .sum()
+    }
+
+    #[test_case(SymbolClass::Punctuation => 123 + 45; "with punctuation")]
+    #[test_case(SymbolClass::OneOf(vec![b'*']) => 123; "with only stars")]
+    #[test_case(SymbolClass::OneOf(vec![b'$', b'x']) => 45 + 6; "with dollars and exes")]
+    #[test_case(SymbolClass::NoneOf(vec![b'*']) => 45 + 6; "with anything but stars")]
+    pub fn symbol_classes(symbols: SymbolClass) -> u32 {
+        part_numbers(MIXED_SYMBOLS, &symbols).iter()
+            .map(|part| part.value)
+            .sum()
+    }
+
+    #[test]
+    pub fn schematic_still_lists_shared_numbers_per_symbol() {
+        let schematic = parse_schematic(SHARED_BY_TWO_SYMBOLS);
+        assert_eq!(schematic[&(0, 0)].1, vec![100]);
+        assert_eq!(schematic[&(0, 4)].1, vec![100]);
+    }
+
     const SAMPLE_INPUT: &str = include_str!("./input/day3-sample.txt");
     const PERSONAL_INPUT: &str = include_str!("./input/day3-real.txt");
+
+    const SHARED_BY_TWO_SYMBOLS: &str = "#100*";
+    const SHARED_DIAGONALLY: &str = "*..$.\n.57..\n.....";
+    const SAME_VALUE_TWICE: &str = "7*7";
+    const EDGES: &str = "1..\n.+.\n..2\n...\n9..";
+    const MIXED_SYMBOLS: &str = "123*..\n......\n45$..6\n....x.";
 }
@@ -3,6 +3,7 @@
 use std::collections::HashSet;
 
 pub struct Card {
+    pub id: usize,
     winning_numbers: HashSet<u32>,
     our_numbers: HashSet<u32>,
 }
@@ -12,13 +13,27 @@ pub fn parse_number_list(input: &str) -> HashSet<u32> {
 }
 
 impl Card {
+    /// Parse a single card, including its number
+    ///
+    /// ```
+    /// # use aoc2023::day4::*;
+    /// let card = Card::parse("Card  12: 41 48 83 86 17 | 83 86  6 31 17  9 48 53");
+    /// assert_eq!(card.id, 12);
+    /// assert_eq!(card.winners(), 4);
+    /// ```
     pub fn parse(line: &str) -> Self {
-        let (_, contents) = line.split_once(':').expect("Should have started with 'Card #:'");
+        let (header, contents) = line.split_once(':').expect("Should have started with 'Card #:'");
         let (winners, ours) = contents.split_once('|').expect("Should have had two sets of numbers");
 
+        let id = header.trim()
+            .strip_prefix("Card")
+            .and_then(|id| id.trim().parse().ok())
+            .expect("Should have had a card number");
+
         Self {
-            winning_numbers: winners.split_whitespace().flat_map(|s| s.parse()).collect(),
-            our_numbers: ours.split_whitespace().flat_map(|s| s.parse()).collect(),
+            id,
+            winning_numbers: parse_number_list(winners),
+            our_numbers: parse_number_list(ours),
         }
     }
 
@@ -35,6 +50,122 @@ impl Card {
     }
 }
 
+/// Copies of one card that were won by holding copies of another
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Award {
+    /// The card that did the winning
+    pub winner: usize,
+    /// The card that was won
+    pub won: usize,
+    /// How many copies were won - one per copy of the winner
+    pub copies: usize,
+}
+
+/// Copies a card would have won if the table didn't run out first
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Overflow {
+    /// The card that did the winning
+    pub winner: usize,
+    /// How many of the following cards were missing from the table
+    pub missing_cards: usize,
+    /// How many copies of the winner were held, e.g. how many copies of
+    /// each missing card were forfeited
+    pub copies: usize,
+}
+
+/// The full outcome of playing a table of scratchcards
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Outcome {
+    /// How many copies of each card (by id) we ended up with, in table order,
+    /// including the original
+    pub copies: Vec<(usize, usize)>,
+    /// Every set of copies that was won, in the order they were won. Following
+    /// these from the first card gives the cascade tree.
+    pub awards: Vec<Award>,
+    /// Wins that ran past the end of the table and were dropped
+    pub overflows: Vec<Overflow>,
+}
+
+impl Outcome {
+    /// The total number of cards held at the end of the game
+    pub fn total(&self) -> usize {
+        self.copies.iter().map(|(_, count)| count).sum()
+    }
+
+    /// How many copies of the given card were held at the end of the game
+    pub fn copies_of(&self, id: usize) -> Option<usize> {
+        self.copies.iter().find(|(card, _)| *card == id).map(|(_, count)| *count)
+    }
+
+    /// Everything won by holding the given card
+    pub fn awarded_by(&self, id: usize) -> impl Iterator<Item = &Award> {
+        self.awards.iter().filter(move |award| award.winner == id)
+    }
+
+    /// Everywhere copies of the given card came from
+    pub fn awarded_to(&self, id: usize) -> impl Iterator<Item = &Award> {
+        self.awards.iter().filter(move |award| award.won == id)
+    }
+}
+
+/// A table of scratchcards played with the copy-winning rules
+pub struct ScratchcardGame {
+    cards: Vec<Card>,
+}
+
+impl From<Vec<Card>> for ScratchcardGame {
+    fn from(cards: Vec<Card>) -> Self {
+        Self { cards }
+    }
+}
+
+impl ScratchcardGame {
+    pub fn parse(input: &str) -> Self {
+        Self::from(input.lines().map(Card::parse).collect::<Vec<_>>())
+    }
+
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+
+    /// Play the game through, tracking how every copy was won. Wins are
+    /// applied to the cards that follow in table order; any that would
+    /// land past the end of the table are recorded as overflows rather
+    /// than counted.
+    ///
+    /// ```
+    /// # use aoc2023::day4::*;
+    /// let game = ScratchcardGame::parse("Card 1: 1 2 | 1 2\nCard 2: 3 | 4");
+    /// let outcome = game.play();
+    /// assert_eq!(outcome.copies, vec![(1, 1), (2, 2)]);
+    /// assert_eq!(outcome.overflows, vec![Overflow { winner: 1, missing_cards: 1, copies: 1 }]);
+    /// ```
+    pub fn play(&self) -> Outcome {
+        let mut counts = vec![1; self.cards.len()];
+        let mut outcome = Outcome::default();
+
+        for (idx, card) in self.cards.iter().enumerate() {
+            let winners = card.winners();
+            let copies = counts[idx];
+
+            // Record n more of each of the following cards, where n is the
+            // number of copies of the current card we have
+            let last = (idx + winners).min(self.cards.len() - 1);
+            for j in idx + 1..=last {
+                counts[j] += copies;
+                outcome.awards.push(Award { winner: card.id, won: self.cards[j].id, copies });
+            }
+
+            if idx + winners > last {
+                outcome.overflows.push(Overflow { winner: card.id, missing_cards: idx + winners - last, copies });
+            }
+        }
+
+        outcome.copies = self.cards.iter().map(|card| card.id).zip(counts).collect();
+        outcome
+    }
+}
+
 #[cfg(test)]
 mod answers {
     use crate::prelude::strings::*;
@@ -50,26 +181,46 @@ mod answers {
     #[test_case(SAMPLE_INPUT => 30; "with sample data")]
     #[test_case(PERSONAL_INPUT => 5571760; "with personal data")]
     pub fn problem2(input: &str) -> usize {
-        input.map_lines(|line| Card::parse(line).winners())
-            .enumerate()
-            .fold(vec![], |mut counts, (idx, winners)| {
-                // Need room for num_hits more elements at least
-                let new_size = counts.len().max(idx + winners + 1);
-                counts.resize(new_size, 1);
-
-                // Record n more of each of the following num_hits cards,
-                // where n is the number of copies of the current card we have
-                for j in 1..= winners {
-                    counts[idx + j] += counts[idx];
-                }
-
-                counts
-            })
-            .iter()
-            .sum()
+        let outcome = ScratchcardGame::parse(input).play();
+        assert!(outcome.overflows.is_empty());
+        outcome.total()
+    }
+
+    #[test]
+    pub fn sample_cascade() {
+        let outcome = ScratchcardGame::parse(SAMPLE_INPUT).play();
+
+        assert_eq!(outcome.copies, vec![(1, 1), (2, 2), (3, 4), (4, 8), (5, 14), (6, 1)]);
+
+        // Card 1 has four winners, so wins one copy each of the next four
+        let from_one = outcome.awarded_by(1).map(|a| (a.won, a.copies)).collect::<Vec<_>>();
+        assert_eq!(from_one, vec![(2, 1), (3, 1), (4, 1), (5, 1)]);
+
+        // Card 2 only reaches as far as card 4, so card 5 never hears from it
+        let to_five = outcome.awarded_to(5).map(|a| (a.winner, a.copies)).collect::<Vec<_>>();
+        assert_eq!(to_five, vec![(1, 1), (3, 4), (4, 8)]);
+
+        // Every copy beyond the originals is accounted for by an award
+        let won = outcome.awards.iter().map(|a| a.copies).sum::<usize>();
+        assert_eq!(outcome.total(), won + 6);
+    }
+
+    #[test]
+    pub fn wins_past_the_end_are_reported() {
+        let input = "Card 1: 1 2 3 | 1 2 3
+                     Card 2: 4 5 | 4 5
+                     Card 3: 6 | 7";
+        let outcome = ScratchcardGame::parse(input).play();
+
+        assert_eq!(outcome.copies, vec![(1, 1), (2, 2), (3, 4)]);
+        assert_eq!(outcome.total(), 7);
+        assert_eq!(outcome.overflows, vec![
+            Overflow { winner: 1, missing_cards: 1, copies: 1 },
+            Overflow { winner: 2, missing_cards: 1, copies: 2 },
+        ]);
     }
 
-    const SAMPLE_INPUT: &str = 
+    const SAMPLE_INPUT: &str =
     "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
     Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
     Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
@@ -132,7 +132,7 @@ mod answers {
             .collect::<Vec<_>>();
 
         let mut x = almanac.process_ranges(seed_ranges);
-        x.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        x.sort_unstable_by_key(|a| a.0);
         x[0].0
     }
 
@@ -1,52 +1,296 @@
 //! Day 8 - Haunted Wasteland
 
-use regex::Regex;
-use std::collections::HashMap;
+use crate::prelude::math::{crt, CrtError};
+use std::{collections::HashMap, fmt::Display};
+
+/// The shape of a walk through the map from a single starting node. Every
+/// walk eventually revisits a (node, instruction) state, after which it
+/// repeats forever.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cycle {
+    /// How many steps are taken before the walk enters its cycle
+    pub prefix: u64,
+    /// How many steps it takes to go around the cycle once
+    pub period: u64,
+    /// Every step, before the walk first comes back around, at which it was
+    /// on a goal node. Those at or after `prefix` recur every `period` steps.
+    pub hits: Vec<u64>,
+}
+
+impl Cycle {
+    /// Is the walk on a goal node after exactly this many steps?
+    pub fn is_hit(&self, step: u64) -> bool {
+        if step < self.prefix {
+            self.hits.binary_search(&step).is_ok()
+        } else {
+            let offset = self.prefix + (step - self.prefix) % self.period;
+            self.hits.binary_search(&offset).is_ok()
+        }
+    }
+
+    /// The hits that recur once the walk is in its cycle
+    pub fn cycle_hits(&self) -> impl Iterator<Item = u64> + '_ {
+        self.hits.iter().copied().filter(|&step| step >= self.prefix)
+    }
+}
+
+/// Find the first step at which every one of the walks is on a goal node
+/// at the same time, if there is one.
+///
+/// ```
+/// # use aoc2023::day8::*;
+/// let a = Cycle { prefix: 1, period: 4, hits: vec![0, 2] };
+/// let b = Cycle { prefix: 0, period: 3, hits: vec![1] };
+/// assert_eq!(first_common_hit(&[a, b]), Some(10));
+/// ```
+pub fn first_common_hit(cycles: &[Cycle]) -> Option<u64> {
+    let longest_prefix = cycles.iter().map(|c| c.prefix).max()?;
+
+    // Before every walk has settled into its cycle there are only finitely
+    // many candidates, so just check them directly
+    if let Some(step) = (0..longest_prefix).find(|&step| cycles.iter().all(|c| c.is_hit(step))) {
+        return Some(step);
+    }
+
+    // After that every walk is periodic, so each one is a set of congruences
+    // that can be folded together, one choice of hit per walk
+    let combined = cycles.iter().try_fold(vec![(0i128, 1i128)], |solutions, cycle| {
+        let period = cycle.period as i128;
+        let mut next = solutions.iter()
+            .flat_map(|&solution| cycle.cycle_hits().filter_map(move |hit| {
+                match crt(solution, (hit as i128, period)) {
+                    Ok(combined) => Some(combined),
+                    Err(CrtError::Inconsistent) => None,
+                    Err(err) => panic!("Couldn't combine cycles: {err}"),
+                }
+            }))
+            .collect::<Vec<_>>();
+        next.sort_unstable();
+        next.dedup();
+        (!next.is_empty()).then_some(next)
+    })?;
+
+    combined.into_iter()
+        .map(|(residue, modulus)| {
+            // Smallest step that's congruent to the residue and not before
+            // every walk has entered its cycle
+            let floor = longest_prefix as i128;
+            let step = if residue >= floor { residue } else { residue + (floor - residue + modulus - 1) / modulus * modulus };
+            step as u64
+        })
+        .min()
+}
+
+/// Which way to go at a fork
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Instruction {
+    Left,
+    Right,
+}
+
+/// A node in the network, as an index into the map it came from
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct NodeId(usize);
+
+/// Ways that the puzzle input can fail to describe a usable map
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// There was no blank line between the instructions and the network
+    MissingNetwork,
+    /// The instruction line was empty
+    NoInstructions,
+    /// An instruction was something other than 'L' or 'R'
+    BadInstruction(char),
+    /// A line of the network wasn't of the form `AAA = (BBB, CCC)`
+    MalformedNode(String),
+    /// The same node was described more than once
+    DuplicateNode(String),
+    /// A node leads to another node that was never described
+    DanglingReference { from: String, to: String },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingNetwork => write!(f, "no network after the instructions"),
+            Self::NoInstructions => write!(f, "no instructions"),
+            Self::BadInstruction(ch) => write!(f, "unknown instruction {ch:?}"),
+            Self::MalformedNode(line) => write!(f, "malformed node {line:?}"),
+            Self::DuplicateNode(label) => write!(f, "node {label} was described twice"),
+            Self::DanglingReference { from, to } => write!(f, "node {from} leads to unknown node {to}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
 
 pub struct Map<'a> {
-    instructions: &'a str,
-    nodes: HashMap<&'a str, (&'a str, &'a str)>,
+    instructions: Vec<Instruction>,
+    labels: Vec<&'a str>,
+    ids: HashMap<&'a str, NodeId>,
+    edges: Vec<(NodeId, NodeId)>,
 }
 
 impl<'a> Map<'a> {
-    pub fn parse(input: &'a str) -> Self {
+    /// Parse the instructions and the network of nodes. Labels can be any
+    /// length, but every node that's referred to must also be described.
+    ///
+    /// ```
+    /// # use aoc2023::day8::*;
+    /// assert!(Map::parse("LR\n\nA = (B, B)\nB = (A, A)").is_ok());
+    /// assert_eq!(Map::parse("LX\n\nA = (A, A)").err(), Some(ParseError::BadInstruction('X')));
+    /// assert_eq!(
+    ///     Map::parse("L\n\nA = (B, A)").err(),
+    ///     Some(ParseError::DanglingReference { from: "A".into(), to: "B".into() }),
+    /// );
+    /// ```
+    pub fn parse(input: &'a str) -> Result<Self, ParseError> {
         // First line is the set of instructions, rest are node mappings
-        let (instructions, rest) = input.split_once("\n\n").expect("Malformed");
-        let re = Regex::new(r"(\w\w\w) = \((\w\w\w), (\w\w\w)\)").expect("Should have compiled");
-        let nodes = re.captures_iter(rest).map(|c| {
-            let (_, [start, left, right]) = c.extract();
-            (start, (left, right))
-        })
-        .collect();
+        let (instructions, rest) = input.split_once("\n\n").ok_or(ParseError::MissingNetwork)?;
+        let instructions = instructions.trim().chars()
+            .map(|ch| match ch {
+                'L' => Ok(Instruction::Left),
+                'R' => Ok(Instruction::Right),
+                other => Err(ParseError::BadInstruction(other)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Self { instructions, nodes }
-    }
+        if instructions.is_empty() {
+            return Err(ParseError::NoInstructions);
+        }
 
-    pub fn count_steps_until<F>(&self, from: &str, until: F) -> u64 where F: Fn(&str) -> bool {
-        let mut current = from;
-        let mut steps = 0;
+        // Intern every described node before resolving where they lead, so
+        // that forward references work
+        let mut descriptions = vec![];
+        for line in rest.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            descriptions.push(Self::parse_node(line).ok_or_else(|| ParseError::MalformedNode(line.to_owned()))?);
+        }
 
-        for x in self.instructions.chars().cycle() {
-            if until(current) {
-                break;
+        let mut labels = vec![];
+        let mut ids = HashMap::new();
+        for &(label, _, _) in &descriptions {
+            if ids.insert(label, NodeId(labels.len())).is_some() {
+                return Err(ParseError::DuplicateNode(label.to_owned()));
             }
+            labels.push(label);
+        }
 
-            if let Some(node) = self.nodes.get(current) {
-                match x {
-                    'L' => current = node.0,
-                    'R' => current = node.1,
-                    _ => unimplemented!(),
-                }
+        let resolve = |from: &str, to: &str| {
+            ids.get(to).copied().ok_or_else(|| ParseError::DanglingReference { from: from.to_owned(), to: to.to_owned() })
+        };
+
+        let edges = descriptions.iter()
+            .map(|&(label, left, right)| Ok((resolve(label, left)?, resolve(label, right)?)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { instructions, labels, ids, edges })
+    }
+
+    fn parse_node(line: &str) -> Option<(&str, &str, &str)> {
+        let is_label = |label: &&str| !label.is_empty() && !label.contains(char::is_whitespace);
+
+        let (label, rest) = line.split_once('=')?;
+        let (left, right) = rest.trim().strip_prefix('(')?.strip_suffix(')')?.split_once(',')?;
+        Some((
+            Some(label.trim()).filter(is_label)?,
+            Some(left.trim()).filter(is_label)?,
+            Some(right.trim()).filter(is_label)?,
+        ))
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Look up a node by its label
+    pub fn node(&self, label: &str) -> Option<NodeId> {
+        self.ids.get(label).copied()
+    }
+
+    pub fn label(&self, node: NodeId) -> &'a str {
+        self.labels[node.0]
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.labels.len()).map(NodeId)
+    }
+
+    /// Where following the given instruction from the given node leads
+    pub fn step(&self, node: NodeId, instruction: Instruction) -> NodeId {
+        match instruction {
+            Instruction::Left => self.edges[node.0].0,
+            Instruction::Right => self.edges[node.0].1,
+        }
+    }
+
+    /// Walk the map forever from the given node, yielding the node we're on
+    /// and the index of the instruction that's about to be followed
+    ///
+    /// ```
+    /// # use aoc2023::day8::*;
+    /// let map = Map::parse("LR\n\nA = (B, A)\nB = (A, C)\nC = (C, C)").unwrap();
+    /// let start = map.node("A").unwrap();
+    /// let labels = map.walk(start).take(4).map(|(node, idx)| (map.label(node), idx)).collect::<Vec<_>>();
+    /// assert_eq!(labels, vec![("A", 0), ("B", 1), ("C", 0), ("C", 1)]);
+    /// ```
+    pub fn walk(&self, from: NodeId) -> Walk<'_, 'a> {
+        Walk { map: self, current: from, idx: 0 }
+    }
+
+    pub fn count_steps_until<F>(&self, from: NodeId, until: F) -> u64 where F: Fn(&str) -> bool {
+        self.walk(from)
+            .position(|(node, _)| until(self.label(node)))
+            .expect("Walks never end") as u64
+    }
+
+    /// Walk from the given node until a (node, instruction) state repeats,
+    /// recording every step where the walk is on a node matching `is_goal`
+    ///
+    /// ```
+    /// # use aoc2023::day8::*;
+    /// let map = Map::parse("LR\n\nAAA = (BBB, XXX)\nBBB = (XXX, ZZZ)\nZZZ = (BBB, XXX)\nXXX = (XXX, XXX)").unwrap();
+    /// let cycle = map.cycle_from(map.node("AAA").unwrap(), |node| node.ends_with('Z'));
+    /// assert_eq!(cycle, Cycle { prefix: 1, period: 2, hits: vec![2] });
+    /// ```
+    pub fn cycle_from<F>(&self, from: NodeId, is_goal: F) -> Cycle where F: Fn(&str) -> bool {
+        // Every state is a (node, instruction) pair, so a flat table of when
+        // each was first seen is cheaper than hashing them
+        let mut seen = vec![None; self.labels.len() * self.instructions.len()];
+        let mut hits = vec![];
+
+        for (step, (node, idx)) in (0..).zip(self.walk(from)) {
+            if let Some(first) = seen[node.0 * self.instructions.len() + idx].replace(step) {
+                return Cycle { prefix: first, period: step - first, hits };
             }
 
-            steps += 1;
+            if is_goal(self.label(node)) {
+                hits.push(step);
+            }
         }
 
-        steps
+        unreachable!("Walks never end")
+    }
+
+    pub fn part2_nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes().filter(|&node| self.label(node).ends_with('A'))
     }
+}
+
+/// An endless walk through a [`Map`], following its instructions
+pub struct Walk<'m, 'a> {
+    map: &'m Map<'a>,
+    current: NodeId,
+    idx: usize,
+}
+
+impl<'m, 'a> Iterator for Walk<'m, 'a> {
+    type Item = (NodeId, usize);
 
-    pub fn part2_nodes(&self) -> impl Iterator<Item = &&str> {
-        self.nodes.keys().filter(|key| key.ends_with('A'))
+    fn next(&mut self) -> Option<Self::Item> {
+        let here = (self.current, self.idx);
+        self.current = self.map.step(self.current, self.map.instructions[self.idx]);
+        self.idx = (self.idx + 1) % self.map.instructions.len();
+        Some(here)
     }
 }
 
@@ -64,16 +308,108 @@ mod answers {
     #[test_case(SAMPLE_INPUT_2 => 6; "with second sample data")]
     #[test_case(PERSONAL_INPUT => 24253; "with personal data")]
     pub fn problem1(input: &str) -> u64 {
-        Map::parse(input).count_steps_until("AAA", |x| x == "ZZZ")
+        let map = Map::parse(input).expect("Should have been a valid map");
+        let start = map.node("AAA").expect("Should have had a starting node");
+        map.count_steps_until(start, |x| x == "ZZZ")
     }
 
     #[test_case(SAMPLE_INPUT_3 => 6; "with first sample data")]
     #[test_case(PERSONAL_INPUT => 12357789728873; "with personal data")]
     pub fn problem2(input: &str) -> u64 {
-        let map = Map::parse(input);
+        let map = Map::parse(input).expect("Should have been a valid map");
+        let cycles = map.part2_nodes()
+            .map(|start| map.cycle_from(start, |x| x.ends_with('Z')))
+            .collect::<Vec<_>>();
+
+        first_common_hit(&cycles).expect("The ghosts should have met up eventually")
+    }
+
+    #[test_case(SAMPLE_INPUT_3 => 6; "with first sample data")]
+    #[test_case(PERSONAL_INPUT => 12357789728873; "with personal data")]
+    pub fn problem2_lcm(input: &str) -> u64 {
+        let map = Map::parse(input).expect("Should have been a valid map");
         map.part2_nodes()
-            .map(|&start| { map.count_steps_until(start, |x| x.ends_with('Z')) })
+            .map(|start| { map.count_steps_until(start, |x| x.ends_with('Z')) })
             .reduce(crate::prelude::math::lcm)
             .expect("Well that's just bad math")
     }
+
+    #[test]
+    pub fn labels_of_any_length() {
+        let map = Map::parse("RL\n\nSTART = (X, LONGER_LABEL)\nX = (X, X)\nLONGER_LABEL = (END, X)\nEND = (END, END)")
+            .expect("Should have been a valid map");
+        let start = map.node("START").expect("Should have had a starting node");
+        assert_eq!(map.count_steps_until(start, |x| x == "END"), 2);
+    }
+
+    #[test_case("LR" => ParseError::MissingNetwork; "without a network")]
+    #[test_case("\n\nAAA = (AAA, AAA)" => ParseError::NoInstructions; "without instructions")]
+    #[test_case("LRU\n\nAAA = (AAA, AAA)" => ParseError::BadInstruction('U'); "with a bad instruction")]
+    #[test_case("L\n\nAAA = AAA, AAA" => ParseError::MalformedNode("AAA = AAA, AAA".into()); "without parentheses")]
+    #[test_case("L\n\nAAA = (AAA)" => ParseError::MalformedNode("AAA = (AAA)".into()); "with one edge")]
+    #[test_case("L\n\n = (AAA, AAA)" => ParseError::MalformedNode("= (AAA, AAA)".into()); "with an empty label")]
+    #[test_case("L\n\nAAA = (AAA, AAA)\nAAA = (AAA, AAA)" => ParseError::DuplicateNode("AAA".into()); "with a duplicate node")]
+    #[test_case("L\n\nAAA = (AAA, BBB)" => ParseError::DanglingReference { from: "AAA".into(), to: "BBB".into() }; "with a dangling reference")]
+    pub fn parse_errors(input: &str) -> ParseError {
+        Map::parse(input).err().expect("Should have failed to parse")
+    }
+
+    // Ghost 1 hits its Z after 2 steps and then every 3, ghost 2 hits after
+    // 1 step and then every 2, so the LCM of first hits (2) is wrong and
+    // the real answer is 5
+    const UNALIGNED: &str = "L
+
+11A = (11B, 11B)
+11B = (11Z, 11Z)
+11Z = (11A, 11A)
+21A = (21Z, 21Z)
+21Z = (21B, 21B)
+21B = (21Z, 21Z)";
+
+    // Ghost 1 visits two different Z nodes per lap, and only the second
+    // one lines up with ghost 2 the first time round (at 3 rather than 9)
+    const MULTIPLE_GOALS: &str = "L
+
+11A = (11Z, 11Z)
+11Z = (11B, 11B)
+11B = (12Z, 12Z)
+12Z = (11C, 11C)
+11C = (11Z, 11Z)
+21A = (21B, 21B)
+21B = (21C, 21C)
+21C = (21Z, 21Z)
+21Z = (21B, 21B)";
+
+    // Ghost 2 wanders through a Z node once before it settles into a loop
+    // that never visits one, so the only meeting point is early on
+    const PREFIX_ONLY: &str = "L
+
+11A = (11Z, 11Z)
+11Z = (11Z, 11Z)
+21A = (21Z, 21Z)
+21Z = (21B, 21B)
+21B = (21B, 21B)";
+
+    // The two ghosts are always on opposite parity, so they never meet
+    const NEVER: &str = "L
+
+11A = (11Z, 11Z)
+11Z = (11B, 11B)
+11B = (11Z, 11Z)
+21A = (21B, 21B)
+21B = (21Z, 21Z)
+21Z = (21B, 21B)";
+
+    #[test_case(UNALIGNED => Some(5); "with unaligned cycles")]
+    #[test_case(MULTIPLE_GOALS => Some(3); "with several goals per cycle")]
+    #[test_case(PREFIX_ONLY => Some(1); "with a hit only in the prefix")]
+    #[test_case(NEVER => None; "with ghosts that never meet")]
+    pub fn problem2_arbitrary(input: &str) -> Option<u64> {
+        let map = Map::parse(input).expect("Should have been a valid map");
+        let cycles = map.part2_nodes()
+            .map(|start| map.cycle_from(start, |x| x.ends_with('Z')))
+            .collect::<Vec<_>>();
+
+        first_common_hit(&cycles)
+    }
 }
@@ -1,22 +1,157 @@
 //! Day 9 - Mirage Maintenance
 
+use std::fmt::Display;
+
 pub fn parse(input: &str) -> Vec<i64> {
     input.split_whitespace().flat_map(|word| word.parse()).collect()
 }
 
-pub fn extrapolate(source: Vec<i64>) -> Vec<Vec<i64>> {
+/// Reasons a set of readings can't be extrapolated
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SequenceError {
+    /// There were no readings at all
+    Empty,
+    /// The differences ran out before reaching a row of zeros. The only
+    /// polynomial through n readings then has degree n - 1, which leaves no
+    /// spare reading to confirm it, e.g. a single non-zero reading or 1, 2, 4.
+    /// Readings that are all zero are always fine, however few there are.
+    NotPolynomial,
+    /// A difference or an extrapolated value didn't fit in an `i64`
+    Overflow,
+}
+
+impl Display for SequenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no readings"),
+            Self::NotPolynomial => write!(f, "readings never reach a row of zero differences"),
+            Self::Overflow => write!(f, "value out of range"),
+        }
+    }
+}
+
+impl std::error::Error for SequenceError {}
+
+/// The next row of the difference pyramid, checking for overflow
+fn differences(row: &[i64]) -> Result<Vec<i64>, SequenceError> {
+    row.iter()
+        .map_windows(|&[a, b]| b.checked_sub(*a).ok_or(SequenceError::Overflow))
+        .collect()
+}
+
+/// Build the difference pyramid for the readings, stopping before the
+/// first row of zeros
+///
+/// ```
+/// # use aoc2023::day9::*;
+/// assert_eq!(extrapolate(vec![1, 3, 5]), Ok(vec![vec![1, 3, 5], vec![2, 2]]));
+/// assert_eq!(extrapolate(vec![0]), Ok(vec![vec![0]]));
+/// assert_eq!(extrapolate(vec![1, 2, 4]), Err(SequenceError::NotPolynomial));
+/// assert_eq!(extrapolate(vec![i64::MIN, i64::MAX]), Err(SequenceError::Overflow));
+/// ```
+pub fn extrapolate(source: Vec<i64>) -> Result<Vec<Vec<i64>>, SequenceError> {
+    if source.is_empty() {
+        return Err(SequenceError::Empty);
+    }
+
+    // However short it is, a row of zeros is the zero polynomial
+    if source.iter().all(|x| x == &0) {
+        return Ok(vec![source]);
+    }
+
     let mut results = vec![source];
 
     loop {
         let current = &results[results.len() - 1];
-        let next = current.iter().map_windows(|&[a, b]| b - a).collect::<Vec<_>>();
+        let next = differences(current)?;
+        if next.is_empty() {
+            return Err(SequenceError::NotPolynomial);
+        }
         if next.iter().all(|x| x == &0) {
             break;
         }
         results.push(next);
     }
 
-    results
+    Ok(results)
+}
+
+/// A sequence of readings, stored as the Newton forward-difference
+/// coefficients of the polynomial that generates it. That is, the first
+/// value of each row of the difference pyramid.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sequence {
+    coefficients: Vec<i64>,
+}
+
+impl Sequence {
+    /// Build the sequence from its readings, which are taken to be at
+    /// indices `0..values.len()`
+    ///
+    /// ```
+    /// # use aoc2023::day9::*;
+    /// let seq = Sequence::new(&[1, 3, 6, 10, 15, 21]).unwrap();
+    /// assert_eq!(seq.degree(), 2);
+    /// assert_eq!(seq.coefficients(), &[1, 2, 1]);
+    /// ```
+    pub fn new(values: &[i64]) -> Result<Self, SequenceError> {
+        if values.is_empty() {
+            return Err(SequenceError::Empty);
+        }
+
+        let mut row = values.to_vec();
+        let mut coefficients = vec![];
+
+        while !row.iter().all(|x| x == &0) {
+            coefficients.push(row[0]);
+            row = differences(&row)?;
+            if row.is_empty() {
+                return Err(SequenceError::NotPolynomial);
+            }
+        }
+
+        Ok(Self { coefficients })
+    }
+
+    /// The degree of the generating polynomial. A sequence of all zeros is
+    /// counted as degree zero.
+    pub fn degree(&self) -> usize {
+        self.coefficients.len().saturating_sub(1)
+    }
+
+    pub fn coefficients(&self) -> &[i64] {
+        &self.coefficients
+    }
+
+    /// Evaluate the generating polynomial at any index, including ones
+    /// before the start or past the end of the readings, in `O(degree)`
+    ///
+    /// ```
+    /// # use aoc2023::day9::*;
+    /// let seq = Sequence::new(&[10, 13, 16, 21, 30, 45]).unwrap();
+    /// assert_eq!(seq.at(6), Ok(68));
+    /// assert_eq!(seq.at(-1), Ok(5));
+    /// assert_eq!(seq.at(3), Ok(21));
+    /// ```
+    pub fn at(&self, index: i64) -> Result<i64, SequenceError> {
+        // Sum of coefficient k times binomial(index, k), building each
+        // binomial from the last. binomial(n, k - 1) * (n - k + 1) is always
+        // k * binomial(n, k), so the division is exact even when n < 0.
+        let index = index as i128;
+        let mut binomial = 1i128;
+        let mut total = 0i128;
+
+        for (k, &coefficient) in self.coefficients.iter().enumerate() {
+            if k > 0 {
+                binomial = binomial.checked_mul(index - k as i128 + 1).ok_or(SequenceError::Overflow)? / k as i128;
+            }
+            total = (coefficient as i128).checked_mul(binomial)
+                .and_then(|term| total.checked_add(term))
+                .ok_or(SequenceError::Overflow)?;
+        }
+
+        i64::try_from(total).map_err(|_| SequenceError::Overflow)
+    }
 }
 
 #[cfg(test)]
@@ -36,8 +171,77 @@ mod answers {
         input.map_lines(|line| {
             let mut seq = parse(line);
             if reverse { seq.reverse(); }
-            extrapolate(seq).iter().map(|v| v[v.len() - 1]).sum::<i64>()
+            extrapolate(seq).expect("Should have been a polynomial").iter().map(|v| v[v.len() - 1]).sum::<i64>()
         })
         .sum()
     }
+
+    #[test_case(SAMPLE_INPUT, false => 114; "with sample input forward")]
+    #[test_case(SAMPLE_INPUT, true => 2; "with sample input reversed")]
+    #[test_case(PERSONAL_INPUT, false => 1974232246; "with personal input forward")]
+    #[test_case(PERSONAL_INPUT, true => 928; "with personal input reversed")]
+    pub fn problem1_closed_form(input: &str, reverse: bool) -> i64 {
+        input.map_lines(|line| {
+            let values = parse(line);
+            let seq = Sequence::new(&values).expect("Should have been a polynomial");
+            let value = if reverse { seq.at(-1) } else { seq.at(values.len() as i64) };
+            value.expect("Should have been in range")
+        })
+        .sum()
+    }
+
+    #[test_case(&[0] => 0; "a single zero")]
+    #[test_case(&[0, 0, 0] => 0; "all zeros")]
+    #[test_case(&[7, 7, 7, 7] => 0; "constant")]
+    #[test_case(&[0, 3, 6, 9, 12, 15] => 1; "linear")]
+    #[test_case(&[1, 3, 6, 10, 15, 21] => 2; "triangular numbers")]
+    #[test_case(&[0, 1, 8, 27, 64, 125, 216] => 3; "cubes")]
+    pub fn degrees(values: &[i64]) -> usize {
+        assert!(extrapolate(values.to_vec()).is_ok());
+        Sequence::new(values).expect("Should have been a polynomial").degree()
+    }
+
+    #[test_case(-5 => -125; "well before the start")]
+    #[test_case(0 => 0; "at the start")]
+    #[test_case(6 => 216; "inside the readings")]
+    #[test_case(100 => 1_000_000; "well past the end")]
+    pub fn cubes_anywhere(index: i64) -> i64 {
+        let seq = Sequence::new(&[0, 1, 8, 27, 64, 125, 216]).expect("Should have been a polynomial");
+        seq.at(index).expect("Should have been in range")
+    }
+
+    #[test_case(&[] => SequenceError::Empty; "with no readings")]
+    #[test_case(&[5] => SequenceError::NotPolynomial; "with a single non-zero reading")]
+    #[test_case(&[1, 2, 4, 8, 16] => SequenceError::NotPolynomial; "with powers of two")]
+    #[test_case(&[3, 1, 4, 1, 5, 9, 2, 6] => SequenceError::NotPolynomial; "with digits of pi")]
+    #[test_case(&[i64::MIN, 0, i64::MAX] => SequenceError::Overflow; "when the first differences overflow")]
+    #[test_case(&[0, i64::MAX, 0] => SequenceError::Overflow; "when the second differences overflow")]
+    pub fn bad_readings(values: &[i64]) -> SequenceError {
+        assert_eq!(extrapolate(values.to_vec()).err(), Sequence::new(values).err());
+        Sequence::new(values).expect_err("Should have been rejected")
+    }
+
+    #[test_case(i64::MAX => SequenceError::Overflow; "far past the end")]
+    #[test_case(i64::MIN => SequenceError::Overflow; "far before the start")]
+    #[test_case(3_000_000 => SequenceError::Overflow; "just past the range of i64")]
+    pub fn values_out_of_range(index: i64) -> SequenceError {
+        let seq = Sequence::new(&[0, 1, 8, 27, 64, 125, 216]).expect("Should have been a polynomial");
+        seq.at(index).expect_err("Should have overflowed")
+    }
+
+    #[test]
+    pub fn matches_repeated_extrapolation() {
+        // Stepping the pyramid forward k times should agree with jumping
+        // straight there
+        for line in PERSONAL_INPUT.lines().take(20) {
+            let mut values = parse(line);
+            let seq = Sequence::new(&values).expect("Should have been a polynomial");
+            for _ in 0..5 {
+                let next = extrapolate(values.clone()).expect("Should have been a polynomial")
+                    .iter().map(|v| v[v.len() - 1]).sum::<i64>();
+                assert_eq!(seq.at(values.len() as i64), Ok(next));
+                values.push(next);
+            }
+        }
+    }
 }
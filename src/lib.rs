@@ -1,11 +1,10 @@
 //! Advent of Code - 2023
 
-#![feature(binary_heap_into_iter_sorted)]
-#![feature(iter_array_chunks)]
+#![cfg_attr(test, feature(binary_heap_into_iter_sorted))]
+#![cfg_attr(test, feature(iter_array_chunks))]
 #![feature(iter_collect_into)]
 #![feature(iter_intersperse)]
 #![feature(iter_map_windows)]
-#![feature(lazy_cell)]
 
 pub mod prelude;
 
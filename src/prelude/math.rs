@@ -1,4 +1,5 @@
-use num_traits::Num;
+use num_traits::{Num, NumCast, PrimInt, Signed};
+use std::fmt::Display;
 
 pub fn gcd<T: Num + Copy>(lhs: T, rhs: T) -> T {
     if rhs.is_zero() {
@@ -8,6 +9,287 @@ pub fn gcd<T: Num + Copy>(lhs: T, rhs: T) -> T {
     }
 }
 
+/// Least common multiple. Divides before multiplying so that the only
+/// way to overflow is for the answer itself to be out of range.
+///
+/// ```
+/// # use aoc2023::prelude::math::lcm;
+/// assert_eq!(lcm(4, 6), 12);
+/// assert_eq!(lcm(0, 6), 0);
+/// assert_eq!(lcm(1u64 << 40, 3u64 << 40), 3u64 << 40);
+/// ```
 pub fn lcm<T: Num + Copy>(lhs: T, rhs: T) -> T {
-    (lhs * rhs) as T / gcd(lhs, rhs) as T
+    if lhs.is_zero() || rhs.is_zero() {
+        T::zero()
+    } else {
+        lhs / gcd(lhs, rhs) * rhs
+    }
+}
+
+/// Least common multiple, or `None` if it doesn't fit in `T`
+///
+/// ```
+/// # use aoc2023::prelude::math::checked_lcm;
+/// assert_eq!(checked_lcm(4u8, 6), Some(12));
+/// assert_eq!(checked_lcm(16u8, 17), None);
+/// ```
+pub fn checked_lcm<T: PrimInt>(lhs: T, rhs: T) -> Option<T> {
+    if lhs.is_zero() || rhs.is_zero() {
+        Some(T::zero())
+    } else {
+        (lhs / gcd(lhs, rhs)).checked_mul(&rhs)
+    }
+}
+
+/// Extended Euclidean algorithm, returning `(g, x, y)` such that
+/// `lhs * x + rhs * y == g`, where `g` is the non-negative gcd of the two
+/// inputs. Returns `None` if any of those don't fit in `T`, which can only
+/// happen when one of the inputs is `T::min_value()`.
+///
+/// ```
+/// # use aoc2023::prelude::math::extended_gcd;
+/// assert_eq!(extended_gcd(240, 46), Some((2, -9, 47)));
+/// assert_eq!(extended_gcd(-4, 6), Some((2, 1, 1)));
+/// assert_eq!(extended_gcd(i32::MIN, 0), None);
+/// ```
+pub fn extended_gcd<T: PrimInt + Signed>(lhs: T, rhs: T) -> Option<(T, T, T)> {
+    let (g, x, y) = extended_gcd_i128(lhs.to_i128()?, rhs.to_i128()?)?;
+    Some((cast(g)?, cast(x)?, cast(y)?))
+}
+
+/// The multiplicative inverse of `value` modulo `modulus`, if it has one
+///
+/// ```
+/// # use aoc2023::prelude::math::mod_inverse;
+/// assert_eq!(mod_inverse(3, 11), Some(4));
+/// assert_eq!(mod_inverse(-3, 11), Some(7));
+/// assert_eq!(mod_inverse(4u32, 6), None);
+/// ```
+pub fn mod_inverse<T: PrimInt>(value: T, modulus: T) -> Option<T> {
+    cast(mod_inverse_i128(value.to_i128()?, positive(modulus)?)?)
+}
+
+/// `base` raised to `exp`, modulo `modulus`. Negative exponents raise the
+/// modular inverse instead, so they only work when `base` has one.
+///
+/// ```
+/// # use aoc2023::prelude::math::mod_pow;
+/// assert_eq!(mod_pow(4, 13, 497), Some(445));
+/// assert_eq!(mod_pow(3, -1, 11), Some(4));
+/// assert_eq!(mod_pow(2, -1, 4), None);
+/// assert_eq!(mod_pow(u64::MAX, 2, u64::MAX - 1), Some(1));
+/// ```
+pub fn mod_pow<T: PrimInt>(base: T, exp: T, modulus: T) -> Option<T> {
+    let modulus = positive(modulus)?;
+    let mut base = base.to_i128()?.rem_euclid(modulus);
+    let mut exp = exp.to_i128()?;
+
+    if exp < 0 {
+        base = mod_inverse_i128(base, modulus)?;
+        exp = exp.checked_neg()?;
+    }
+
+    let mut result = 1 % modulus;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, modulus);
+        }
+        base = mul_mod(base, base, modulus);
+        exp >>= 1;
+    }
+
+    cast(result)
+}
+
+/// Why a set of congruences couldn't be combined
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CrtError {
+    /// A modulus was zero or negative
+    InvalidModulus,
+    /// There's no number that satisfies all of the congruences
+    Inconsistent,
+    /// The combined modulus doesn't fit in the integer type
+    Overflow,
+}
+
+impl Display for CrtError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidModulus => write!(f, "moduli must be positive"),
+            Self::Inconsistent => write!(f, "congruences have no common solution"),
+            Self::Overflow => write!(f, "combined modulus is out of range"),
+        }
+    }
+}
+
+impl std::error::Error for CrtError {}
+
+/// Combine the congruences `x = a (mod m)` and `x = b (mod n)` into a
+/// single `x = c (mod lcm(m, n))`, with `0 <= c < lcm(m, n)`. The moduli
+/// don't need to be coprime.
+///
+/// ```
+/// # use aoc2023::prelude::math::*;
+/// assert_eq!(crt((2, 3), (3, 5)), Ok((8, 15)));
+/// assert_eq!(crt((1, 4), (3, 6)), Ok((9, 12)));
+/// assert_eq!(crt((1, 4), (2, 6)), Err(CrtError::Inconsistent));
+/// assert_eq!(crt((0u8, 16), (0, 17)), Err(CrtError::Overflow));
+/// ```
+pub fn crt<T: PrimInt>((a, m): (T, T), (b, n): (T, T)) -> Result<(T, T), CrtError> {
+    let m = m.to_i128().ok_or(CrtError::Overflow)?;
+    let n = n.to_i128().ok_or(CrtError::Overflow)?;
+    if m <= 0 || n <= 0 {
+        return Err(CrtError::InvalidModulus);
+    }
+
+    let a = a.to_i128().ok_or(CrtError::Overflow)?.rem_euclid(m);
+    let b = b.to_i128().ok_or(CrtError::Overflow)?.rem_euclid(n);
+
+    let (g, p, _) = extended_gcd_i128(m, n).ok_or(CrtError::Overflow)?;
+    let diff = b - a;
+    if diff % g != 0 {
+        return Err(CrtError::Inconsistent);
+    }
+
+    // m * p = g (mod n), so stepping a by m * k, where k = p * diff / g,
+    // lands on b (mod n) without disturbing anything (mod m)
+    let reduced = n / g;
+    let modulus = (m / g).checked_mul(n).ok_or(CrtError::Overflow)?;
+    let k = mul_mod((diff / g).rem_euclid(reduced), p.rem_euclid(reduced), reduced);
+
+    // k < n / g, so a + m * k < m + modulus - m and nothing here can overflow
+    let residue = a + m * k;
+    Ok((cast(residue).ok_or(CrtError::Overflow)?, cast(modulus).ok_or(CrtError::Overflow)?))
+}
+
+/// Fold any number of congruences together with [`crt`]. An empty set is
+/// satisfied by everything, e.g. `x = 0 (mod 1)`.
+///
+/// ```
+/// # use aoc2023::prelude::math::*;
+/// assert_eq!(crt_all([(2, 3), (3, 5), (2, 7)]), Ok((23, 105)));
+/// assert_eq!(crt_all::<i64>([]), Ok((0, 1)));
+/// ```
+pub fn crt_all<T: PrimInt>(congruences: impl IntoIterator<Item = (T, T)>) -> Result<(T, T), CrtError> {
+    congruences.into_iter().try_fold((T::zero(), T::one()), crt)
+}
+
+fn cast<T: NumCast>(value: i128) -> Option<T> {
+    T::from(value)
+}
+
+fn positive<T: PrimInt>(value: T) -> Option<i128> {
+    value.to_i128().filter(|&v| v > 0)
+}
+
+fn extended_gcd_i128(lhs: i128, rhs: i128) -> Option<(i128, i128, i128)> {
+    let (mut old_r, mut r) = (lhs, rhs);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    let (mut old_t, mut t) = (0i128, 1i128);
+
+    while r != 0 {
+        let q = old_r.checked_div(r)?;
+        (old_r, r) = (r, old_r.checked_sub(q.checked_mul(r)?)?);
+        (old_s, s) = (s, old_s.checked_sub(q.checked_mul(s)?)?);
+        (old_t, t) = (t, old_t.checked_sub(q.checked_mul(t)?)?);
+    }
+
+    if old_r < 0 {
+        Some((old_r.checked_neg()?, old_s.checked_neg()?, old_t.checked_neg()?))
+    } else {
+        Some((old_r, old_s, old_t))
+    }
+}
+
+fn mod_inverse_i128(value: i128, modulus: i128) -> Option<i128> {
+    let (g, x, _) = extended_gcd_i128(value.rem_euclid(modulus), modulus)?;
+    (g == 1).then(|| x.rem_euclid(modulus))
+}
+
+/// Multiply two values that are already reduced modulo a positive
+/// `modulus`, falling back to double-and-add if the product would overflow
+fn mul_mod(lhs: i128, rhs: i128, modulus: i128) -> i128 {
+    let (lhs, rhs, modulus) = (lhs as u128, rhs as u128, modulus as u128);
+    if let Some(product) = lhs.checked_mul(rhs) {
+        return (product % modulus) as i128;
+    }
+
+    // Both operands are below 2^127, so doubling or adding never overflows
+    let (mut result, mut lhs, mut rhs) = (0u128, lhs, rhs);
+    while rhs > 0 {
+        if rhs & 1 == 1 {
+            result = (result + lhs) % modulus;
+        }
+        lhs = (lhs << 1) % modulus;
+        rhs >>= 1;
+    }
+
+    result as i128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(0, 0 => 0; "both zero")]
+    #[test_case(u64::MAX, u64::MAX => u64::MAX; "at the top of the range")]
+    #[test_case(1 << 63, 1 << 62 => 1 << 63; "when the naive product overflows")]
+    pub fn lcm_without_overflow(lhs: u64, rhs: u64) -> u64 {
+        lcm(lhs, rhs)
+    }
+
+    #[test_case(i128::MAX, i128::MAX - 1; "largest i128s")]
+    #[test_case(i128::MIN + 1, i128::MAX; "smallest and largest i128s")]
+    #[test_case(-12, 0; "negative with zero")]
+    #[test_case(0, 0; "both zero")]
+    pub fn extended_gcd_identity(lhs: i128, rhs: i128) {
+        let (g, x, y) = extended_gcd(lhs, rhs).expect("Should have fit");
+        assert!(g >= 0);
+        assert_eq!(lhs.checked_mul(x).and_then(|a| rhs.checked_mul(y).and_then(|b| a.checked_add(b))), Some(g));
+    }
+
+    #[test_case(3, 1_000_000_007 => Some(333_333_336); "with a prime modulus")]
+    #[test_case(i64::MAX, i64::MAX - 1 => Some(1); "near the top of the range")]
+    #[test_case(0, 7 => None; "with zero")]
+    #[test_case(5, 0 => None; "with a zero modulus")]
+    #[test_case(5, -7 => None; "with a negative modulus")]
+    pub fn inverses(value: i64, modulus: i64) -> Option<i64> {
+        mod_inverse(value, modulus)
+    }
+
+    #[test_case(2, 0, 1 => Some(0); "everything is zero mod one")]
+    #[test_case(0, 0, 7 => Some(1); "zero to the zero")]
+    #[test_case(-2, 3, 7 => Some(6); "with a negative base")]
+    #[test_case(2, 127, i128::MAX => Some(1); "with a mersenne modulus")]
+    #[test_case(i128::MAX - 1, i128::MAX, i128::MAX => Some(i128::MAX - 1); "when squares overflow")]
+    pub fn powers(base: i128, exp: i128, modulus: i128) -> Option<i128> {
+        mod_pow(base, exp, modulus)
+    }
+
+    #[test_case((3, 4), (4, 6) => Err(CrtError::Inconsistent); "with mismatched parity")]
+    #[test_case((3, 4), (7, 6) => Ok((7, 12)); "with a shared factor")]
+    #[test_case((-1, 4), (-1, 6) => Ok((11, 12)); "with negative residues")]
+    #[test_case((5, 3), (5, 3) => Ok((2, 3)); "with a repeated congruence")]
+    #[test_case((1, 0), (1, 3) => Err(CrtError::InvalidModulus); "with a zero modulus")]
+    #[test_case((1, i64::MAX), (0, i64::MAX - 1) => Err(CrtError::Overflow); "when the modulus overflows")]
+    pub fn congruences(lhs: (i64, i64), rhs: (i64, i64)) -> Result<(i64, i64), CrtError> {
+        crt(lhs, rhs)
+    }
+
+    #[test]
+    pub fn crt_at_the_edge_of_i128() {
+        // Two large coprime moduli whose product still fits
+        let m = (1i128 << 62) - 57;
+        let n = (1i128 << 64) - 59;
+        let (x, modulus) = crt((m - 1, m), (n - 1, n)).expect("Should have fit");
+        assert_eq!(modulus, m * n);
+        assert_eq!(x, m * n - 1);
+    }
+
+    #[test]
+    pub fn crt_with_unsigned() {
+        assert_eq!(crt((u64::MAX - 1, u64::MAX), (0, 1)), Ok((u64::MAX - 1, u64::MAX)));
+        assert_eq!(crt((0u128, u128::MAX), (0, 1)), Err(CrtError::Overflow));
+    }
 }
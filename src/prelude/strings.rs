@@ -19,16 +19,16 @@ impl<'a, F, B> Iterator for MapLines<'a, F> where F: FnMut(&str) -> B {
 }
 
 pub trait StrExt<'a> {
-    fn trimmed_lines(&self) -> TrimmedLines;
-    fn map_lines<F, B>(&self, f: F) -> MapLines<F> where F: FnMut(&str) -> B;
+    fn trimmed_lines(&self) -> TrimmedLines<'_>;
+    fn map_lines<F, B>(&self, f: F) -> MapLines<'_, F> where F: FnMut(&str) -> B;
 }
 
 impl<'a, T: AsRef<str>> StrExt<'a> for T {
-    fn trimmed_lines(&self) -> TrimmedLines {
+    fn trimmed_lines(&self) -> TrimmedLines<'_> {
         TrimmedLines(self.as_ref().lines())
     }
 
-    fn map_lines<F, B>(&self, f: F) -> MapLines<F> where F: FnMut(&str) -> B {
+    fn map_lines<F, B>(&self, f: F) -> MapLines<'_, F> where F: FnMut(&str) -> B {
         MapLines(self.as_ref().lines().map(f))
     }
 }